@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+/// Exponential backoff between restarts of a crashing command.
+pub struct Backoff {
+    initial: Duration,
+    multiplier: f64,
+    max: Duration,
+    reset_after: Duration,
+    current: Option<Duration>,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, multiplier: f64, max: Duration, reset_after: Duration) -> Self {
+        Self {
+            initial,
+            multiplier,
+            max,
+            reset_after,
+            current: None,
+        }
+    }
+
+    /// Delay before the next restart of a command that ran for `uptime`.
+    pub fn next(&mut self, uptime: Duration) -> Duration {
+        if uptime >= self.reset_after {
+            self.reset();
+        }
+        let delay = match self.current {
+            None => self.initial,
+            // A multiplier below 1 (or NaN) would shrink the delay, and one
+            // too big to represent saturates at the maximum.
+            Some(current) => {
+                Duration::try_from_secs_f64(current.as_secs_f64() * self.multiplier.max(1.0))
+                    .unwrap_or(self.max)
+            }
+        }
+        .min(self.max);
+        self.current = Some(delay);
+        delay
+    }
+
+    /// Forget previous crashes, the next delay will be the initial one.
+    pub fn reset(&mut self) {
+        self.current = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn backoff() -> Backoff {
+        Backoff::new(ms(100), 2.0, ms(1000), ms(5000))
+    }
+
+    #[test]
+    fn growth() {
+        let mut backoff = backoff();
+        let delays: Vec<_> = (0..4).map(|_| backoff.next(ms(10))).collect();
+        assert_eq!(delays, [ms(100), ms(200), ms(400), ms(800)]);
+
+        let mut backoff = Backoff::new(ms(100), 1.5, ms(1000), ms(5000));
+        let delays: Vec<_> = (0..3).map(|_| backoff.next(ms(10))).collect();
+        assert_eq!(delays, [ms(100), ms(150), ms(225)]);
+    }
+
+    #[test]
+    fn capped() {
+        let mut backoff = backoff();
+        let delays: Vec<_> = (0..7).map(|_| backoff.next(ms(10))).collect();
+        assert_eq!(
+            delays,
+            [
+                ms(100),
+                ms(200),
+                ms(400),
+                ms(800),
+                ms(1000),
+                ms(1000),
+                ms(1000)
+            ]
+        );
+
+        let mut backoff = Backoff::new(ms(2000), 2.0, ms(1000), ms(5000));
+        assert_eq!(backoff.next(ms(10)), ms(1000));
+
+        let mut backoff = Backoff::new(ms(100), f64::MAX, ms(1000), ms(5000));
+        assert_eq!(backoff.next(ms(10)), ms(100));
+        assert_eq!(backoff.next(ms(10)), ms(1000));
+        assert_eq!(backoff.next(ms(10)), ms(1000));
+    }
+
+    #[test]
+    fn reset_after_uptime() {
+        let mut backoff = backoff();
+        backoff.next(ms(10));
+        backoff.next(ms(10));
+        assert_eq!(backoff.next(ms(4999)), ms(400));
+        assert_eq!(backoff.next(ms(5000)), ms(100));
+        assert_eq!(backoff.next(ms(10)), ms(200));
+    }
+
+    #[test]
+    fn reset() {
+        let mut backoff = backoff();
+        for _ in 0..5 {
+            backoff.next(ms(10));
+        }
+        backoff.reset();
+        assert_eq!(backoff.next(ms(10)), ms(100));
+        assert_eq!(backoff.next(ms(10)), ms(200));
+    }
+}
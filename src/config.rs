@@ -0,0 +1,770 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use clap::ArgEnum;
+use rstrtr::{Listen, Probe, Proxy, RestartPolicy};
+
+pub const FILE_NAME: &str = "rstrtr.toml";
+
+/// Project settings read from `rstrtr.toml`, relative paths are resolved
+/// against the directory containing it.
+#[derive(Default)]
+pub struct Config {
+    pub dir: PathBuf,
+    /// The command to run, or the environment, directory and restart policy
+    /// shared by `processes`.
+    pub defaults: ProcessConfig,
+    pub watch: Vec<PathBuf>,
+    pub ignore: Vec<String>,
+    pub include: Vec<String>,
+    pub control: Option<PathBuf>,
+    pub procfile: Option<PathBuf>,
+    /// `[process.NAME]` tables, adding to or refining the Procfile's.
+    pub processes: Vec<ProcessConfig>,
+}
+
+#[derive(Clone, Default)]
+pub struct ProcessConfig {
+    pub name: String,
+    pub command: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub cwd: Option<PathBuf>,
+    pub restart: Option<RestartPolicy>,
+    pub ready: Option<Probe>,
+    pub liveness: Option<Probe>,
+    pub listen: Vec<Listen>,
+    pub proxy: Vec<Proxy>,
+}
+
+impl Config {
+    /// Find `rstrtr.toml` in `dir` or the nearest parent containing one.
+    pub fn discover(dir: &Path) -> anyhow::Result<Option<Self>> {
+        for dir in dir.ancestors() {
+            let path = dir.join(FILE_NAME);
+            if path.is_file() {
+                return Self::load(&path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let src = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Error reading {}: {}", path.display(), e))?;
+        let dir = path
+            .canonicalize()?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        Self::parse(&src, dir).map_err(|(line, e)| anyhow!("{}:{}: {}", path.display(), line, e))
+    }
+
+    fn parse(src: &str, dir: PathBuf) -> Result<Self, (usize, String)> {
+        let mut config = Config {
+            dir,
+            ..Config::default()
+        };
+        let mut line = 0;
+        for entry in Parser::new(src).parse()? {
+            line = entry.line;
+            let keys: Vec<&str> = entry.key.iter().map(String::as_str).collect();
+            let dir = &config.dir;
+            let res = match keys.as_slice() {
+                ["watch"] => strings(entry.value)
+                    .map(|watch| config.watch = watch.iter().map(|path| dir.join(path)).collect())
+                    .map_err(Some),
+                ["ignore"] => strings(entry.value)
+                    .map(|ignore| config.ignore = ignore)
+                    .map_err(Some),
+                ["include"] => strings(entry.value)
+                    .map(|include| config.include = include)
+                    .map_err(Some),
+                ["control"] => string(entry.value)
+                    .map(|control| config.control = Some(dir.join(control)))
+                    .map_err(Some),
+                ["procfile"] => string(entry.value)
+                    .map(|procfile| config.procfile = Some(dir.join(procfile)))
+                    .map_err(Some),
+                ["process", name, key @ ..] => {
+                    let process = match config.processes.iter().position(|p| p.name == *name) {
+                        Some(i) => &mut config.processes[i],
+                        None => {
+                            config.processes.push(ProcessConfig {
+                                name: name.to_string(),
+                                ..ProcessConfig::default()
+                            });
+                            config.processes.last_mut().unwrap()
+                        }
+                    };
+                    process.set(key, entry.value, dir)
+                }
+                key => config.defaults.set(key, entry.value, dir),
+            };
+            res.map_err(|e| match e {
+                Some(e) => (line, e),
+                None => (line, format!("unknown key `{}`", entry.key.join("."))),
+            })?;
+        }
+        if !config.defaults.command.is_empty()
+            && (config.procfile.is_some() || !config.processes.is_empty())
+        {
+            return Err((
+                line,
+                "set either `command` or processes, not both".to_string(),
+            ));
+        }
+        Ok(config)
+    }
+}
+
+impl ProcessConfig {
+    /// Set `key`, failing with `None` if it is unknown.
+    fn set(&mut self, key: &[&str], value: Value, dir: &Path) -> Result<(), Option<String>> {
+        match key {
+            ["command"] => match value {
+                Value::String(s) => {
+                    self.command = vec!["sh".to_string(), "-c".to_string(), s];
+                    Ok(())
+                }
+                value => strings(value).map(|command| self.command = command),
+            },
+            ["env", name] => string(value).map(|value| self.env.push((name.to_string(), value))),
+            ["cwd"] => string(value).map(|cwd| self.cwd = Some(dir.join(cwd))),
+            ["restart"] => string(value).and_then(|restart| {
+                self.restart = Some(
+                    RestartPolicy::from_str(&restart, false)
+                        .map_err(|_| format!("invalid restart policy `{}`", restart))?,
+                );
+                Ok(())
+            }),
+            ["ready"] => string(value).and_then(|ready| {
+                self.ready = Some(ready.parse().map_err(|e| format!("{}", e))?);
+                Ok(())
+            }),
+            ["listen"] => strings(value).and_then(|listen| {
+                self.listen = listen
+                    .iter()
+                    .map(|listen| listen.parse().map_err(|e| format!("{}", e)))
+                    .collect::<Result<_, _>>()?;
+                Ok(())
+            }),
+            ["proxy"] => strings(value).and_then(|proxy| {
+                self.proxy = proxy
+                    .iter()
+                    .map(|proxy| proxy.parse().map_err(|e| format!("{}", e)))
+                    .collect::<Result<_, _>>()?;
+                Ok(())
+            }),
+            ["liveness"] => string(value).and_then(|liveness| {
+                self.liveness = Some(liveness.parse().map_err(|e| format!("{}", e))?);
+                Ok(())
+            }),
+            _ => return Err(None),
+        }
+        .map_err(Some)
+    }
+
+    /// Override these settings with those given in `other`.
+    pub fn merge(&mut self, other: &ProcessConfig) {
+        if !other.command.is_empty() {
+            self.command = other.command.clone();
+        }
+        self.env.extend(other.env.iter().cloned());
+        self.cwd = other.cwd.clone().or_else(|| self.cwd.take());
+        self.restart = other.restart.or(self.restart);
+        self.ready = other.ready.clone().or_else(|| self.ready.take());
+        self.liveness = other.liveness.clone().or_else(|| self.liveness.take());
+        if !other.listen.is_empty() {
+            self.listen = other.listen.clone();
+        }
+        if !other.proxy.is_empty() {
+            self.proxy = other.proxy.clone();
+        }
+    }
+}
+
+fn string(value: Value) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s),
+        value => Err(format!("expected a string, found {}", value.kind())),
+    }
+}
+
+fn strings(value: Value) -> Result<Vec<String>, String> {
+    match value {
+        Value::Array(values) => values.into_iter().map(string).collect(),
+        value => Err(format!(
+            "expected an array of strings, found {}",
+            value.kind()
+        )),
+    }
+}
+
+/// A key, dotted with the table it's in, and its value.
+struct Entry {
+    key: Vec<String>,
+    value: Value,
+    line: usize,
+}
+
+/// A parsed value, only strings are used so the others aren't kept.
+enum Value {
+    String(String),
+    Integer,
+    Float,
+    Boolean,
+    Array(Vec<Value>),
+    Table(Vec<(Vec<String>, Value)>),
+}
+
+impl Value {
+    fn kind(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer => "an integer",
+            Value::Float => "a float",
+            Value::Boolean => "a boolean",
+            Value::Array(_) => "an array",
+            Value::Table(_) => "a table",
+        }
+    }
+}
+
+/// Parses the subset of TOML rstrtr needs: tables, dotted keys, strings,
+/// numbers, booleans, arrays and inline tables.
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(src: &'a str) -> Self {
+        Self {
+            src,
+            pos: 0,
+            line: 1,
+        }
+    }
+
+    fn parse(mut self) -> Result<Vec<Entry>, (usize, String)> {
+        self.entries().map_err(|e| (self.line, e))
+    }
+
+    fn entries(&mut self) -> Result<Vec<Entry>, String> {
+        let mut entries = Vec::new();
+        let mut seen = HashSet::new();
+        let mut table = Vec::new();
+        loop {
+            self.skip_blank(true);
+            match self.peek() {
+                None => return Ok(entries),
+                Some('[') => {
+                    self.bump();
+                    if self.peek() == Some('[') {
+                        return Err("arrays of tables are not supported".to_string());
+                    }
+                    self.skip_blank(false);
+                    table = self.key()?;
+                    self.skip_blank(false);
+                    self.expect(']')?;
+                    if !seen.insert(table.clone()) {
+                        return Err(format!("duplicate table `{}`", table.join(".")));
+                    }
+                }
+                Some(_) => {
+                    let line = self.line;
+                    let (key, value) = self.key_value()?;
+                    let mut key = [table.clone(), key].concat();
+                    let mut flat = Vec::new();
+                    flatten(&mut key, value, &mut flat);
+                    for (key, value) in flat {
+                        if !seen.insert(key.clone()) {
+                            return Err(format!("duplicate key `{}`", key.join(".")));
+                        }
+                        entries.push(Entry { key, value, line });
+                    }
+                }
+            }
+            self.skip_blank(false);
+            match self.peek() {
+                None | Some('\n') => {}
+                Some(c) => return Err(format!("expected end of line, found {:?}", c)),
+            }
+        }
+    }
+
+    fn key_value(&mut self) -> Result<(Vec<String>, Value), String> {
+        let key = self.key()?;
+        self.skip_blank(false);
+        self.expect('=')?;
+        self.skip_blank(false);
+        Ok((key, self.value()?))
+    }
+
+    /// A possibly dotted key.
+    fn key(&mut self) -> Result<Vec<String>, String> {
+        let mut key = Vec::new();
+        loop {
+            key.push(match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                _ => {
+                    let word =
+                        self.take_while(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+                    if word.is_empty() {
+                        return Err(self.unexpected("a key"));
+                    }
+                    word.to_string()
+                }
+            });
+            self.skip_blank(false);
+            if self.peek() != Some('.') {
+                return Ok(key);
+            }
+            self.bump();
+            self.skip_blank(false);
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            Some(c) if c.is_ascii_alphanumeric() || "+-.".contains(c) => {
+                let word = self.take_while(|c| c.is_ascii_alphanumeric() || "+-._".contains(c));
+                match word {
+                    "true" | "false" => Ok(Value::Boolean),
+                    word => {
+                        let num = word.replace('_', "");
+                        if num.parse::<i64>().is_ok() {
+                            Ok(Value::Integer)
+                        } else if num.parse::<f64>().is_ok() {
+                            Ok(Value::Float)
+                        } else {
+                            Err(format!("invalid value `{}`", word))
+                        }
+                    }
+                }
+            }
+            _ => Err(self.unexpected("a value")),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        loop {
+            self.skip_blank(true);
+            if self.peek() == Some(']') {
+                break;
+            }
+            values.push(self.value()?);
+            self.skip_blank(true);
+            match self.peek() {
+                Some(',') => self.bump(),
+                Some(']') => break,
+                _ => return Err(self.unexpected("`,` or `]`")),
+            }
+        }
+        self.bump();
+        Ok(Value::Array(values))
+    }
+
+    fn inline_table(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut entries = Vec::new();
+        self.skip_blank(false);
+        if self.peek() != Some('}') {
+            loop {
+                self.skip_blank(false);
+                entries.push(self.key_value()?);
+                self.skip_blank(false);
+                match self.peek() {
+                    Some(',') => self.bump(),
+                    Some('}') => break,
+                    _ => return Err(self.unexpected("`,` or `}`")),
+                }
+            }
+        }
+        self.bump();
+        Ok(Value::Table(entries))
+    }
+
+    fn basic_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        if self.rest().starts_with("\"\"") {
+            return Err("multi-line strings are not supported".to_string());
+        }
+        let mut s = String::new();
+        loop {
+            let c = match self.peek() {
+                None | Some('\n') => return Err("unterminated string".to_string()),
+                Some(c) => c,
+            };
+            self.bump();
+            match c {
+                '"' => return Ok(s),
+                '\\' => {
+                    let c = self.peek().ok_or("unterminated string")?;
+                    self.bump();
+                    s.push(match c {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        '"' => '"',
+                        '\\' => '\\',
+                        'u' | 'U' => {
+                            let len = if c == 'u' { 4 } else { 8 };
+                            let hex = self.rest().get(..len).unwrap_or_default();
+                            let c = u32::from_str_radix(hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| {
+                                    format!("invalid unicode escape `\\{}{}`", c, hex)
+                                })?;
+                            self.pos += len;
+                            c
+                        }
+                        c => return Err(format!("invalid escape `\\{}`", c)),
+                    });
+                }
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, String> {
+        self.expect('\'')?;
+        let s = self.take_while(|c| c != '\'' && c != '\n').to_string();
+        self.expect('\'')?;
+        Ok(s)
+    }
+
+    /// Skip spaces, tabs and comments, and newlines too if `newlines`.
+    fn skip_blank(&mut self, newlines: bool) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\r') => self.bump(),
+                Some('\n') if newlines => self.bump(),
+                Some('#') => {
+                    self.take_while(|c| c != '\n');
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+        let rest = self.rest();
+        let len = rest.find(|c| !f(c)).unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.peek() != Some(c) {
+            return Err(self.unexpected(&format!("`{}`", c)));
+        }
+        self.bump();
+        Ok(())
+    }
+
+    fn unexpected(&self, expected: &str) -> String {
+        match self.peek() {
+            Some(c) => format!("expected {}, found {:?}", expected, c),
+            None => format!("expected {}, found end of file", expected),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) {
+        if let Some(c) = self.peek() {
+            if c == '\n' {
+                self.line += 1;
+            }
+            self.pos += c.len_utf8();
+        }
+    }
+}
+
+/// Expand inline tables into one entry per dotted key.
+fn flatten(key: &mut Vec<String>, value: Value, out: &mut Vec<(Vec<String>, Value)>) {
+    match value {
+        Value::Table(entries) => {
+            for (sub, value) in entries {
+                let len = key.len();
+                key.extend(sub);
+                flatten(key, value, out);
+                key.truncate(len);
+            }
+        }
+        value => out.push((key.clone(), value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(src: &str) -> Result<Config, (usize, String)> {
+        Config::parse(src, PathBuf::from("/project"))
+    }
+
+    /// The line and message `src` fails to parse with.
+    fn error(src: &str) -> (usize, String) {
+        match parse(src) {
+            Ok(_) => panic!("parsed {:?}", src),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn settings() {
+        let config = parse(
+            r#"
+# comment
+command = ["cargo", "run"]  # trailing comment
+watch = ["src", 'tests']
+ignore = [
+    "*.tmp",
+    "target/**",  # trailing comma
+]
+include = []
+control = ".rstrtr"
+restart = "on-failure"
+ready = "tcp:8080"
+liveness = "http://localhost:8080/health"
+listen = ["tcp:9000", "unix:app.sock"]
+proxy = ["8000=8001"]
+cwd = "sub"
+env.RUST_LOG = "debug"
+env = { A = "1", "B" = '2' }
+"#,
+        )
+        .unwrap();
+        let defaults = &config.defaults;
+        assert_eq!(defaults.command, ["cargo", "run"]);
+        assert_eq!(
+            config.watch,
+            [
+                PathBuf::from("/project/src"),
+                PathBuf::from("/project/tests")
+            ]
+        );
+        assert_eq!(config.ignore, ["*.tmp", "target/**"]);
+        assert!(config.include.is_empty());
+        assert_eq!(config.control, Some(PathBuf::from("/project/.rstrtr")));
+        assert_eq!(defaults.restart, Some(RestartPolicy::OnFailure));
+        assert!(matches!(&defaults.ready, Some(Probe::Tcp(addr)) if addr == "localhost:8080"));
+        assert!(matches!(&defaults.liveness, Some(Probe::Http { path, .. }) if path == "/health"));
+        assert_eq!(
+            defaults.listen,
+            [
+                Listen::Tcp("0.0.0.0:9000".to_string()),
+                Listen::Unix("app.sock".into())
+            ]
+        );
+        assert_eq!(defaults.proxy, ["8000=8001".parse::<Proxy>().unwrap()]);
+        assert_eq!(defaults.cwd, Some(PathBuf::from("/project/sub")));
+        assert_eq!(
+            defaults.env,
+            [
+                ("RUST_LOG".to_string(), "debug".to_string()),
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn command_string_runs_in_shell() {
+        let config = parse("command = 'cargo run | tee log'").unwrap();
+        assert_eq!(config.defaults.command, ["sh", "-c", "cargo run | tee log"]);
+    }
+
+    #[test]
+    fn strings() {
+        let config = parse(
+            r#"
+env.BASIC = "tab\tquote\"backslash\\newline\n\u00e9\U0001F600"
+env.LITERAL = 'C:\no\escapes'
+"env"."QUOTED KEY" = ""
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.defaults.env,
+            [
+                (
+                    "BASIC".to_string(),
+                    "tab\tquote\"backslash\\newline\n\u{e9}\u{1F600}".to_string()
+                ),
+                ("LITERAL".to_string(), "C:\\no\\escapes".to_string()),
+                ("QUOTED KEY".to_string(), "".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn processes() {
+        let config = parse(
+            r#"
+procfile = "Procfile"
+env.SHARED = "1"
+
+[process.web]
+command = "python -m http.server"
+env.PORT = "8000"
+
+[process . "worker"]
+restart = "never"
+"#,
+        )
+        .unwrap();
+        assert!(config.defaults.command.is_empty());
+        assert_eq!(config.procfile, Some(PathBuf::from("/project/Procfile")));
+        assert_eq!(config.processes.len(), 2);
+        let web = &config.processes[0];
+        assert_eq!(web.name, "web");
+        assert_eq!(web.command, ["sh", "-c", "python -m http.server"]);
+        assert_eq!(web.env, [("PORT".to_string(), "8000".to_string())]);
+        let worker = &config.processes[1];
+        assert_eq!(worker.name, "worker");
+        assert_eq!(worker.restart, Some(RestartPolicy::Never));
+    }
+
+    #[test]
+    fn merge() {
+        let mut defaults = ProcessConfig {
+            command: vec!["a".to_string()],
+            env: vec![("A".to_string(), "1".to_string())],
+            restart: Some(RestartPolicy::Always),
+            ..ProcessConfig::default()
+        };
+        defaults.merge(&ProcessConfig {
+            env: vec![("B".to_string(), "2".to_string())],
+            restart: Some(RestartPolicy::Never),
+            ..ProcessConfig::default()
+        });
+        assert_eq!(defaults.command, ["a"]);
+        assert_eq!(defaults.env.len(), 2);
+        assert_eq!(defaults.restart, Some(RestartPolicy::Never));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let cases = [
+            ("[[process]]", "arrays of tables are not supported"),
+            ("= 'a'", "expected a key, found '='"),
+            ("command 'a'", "expected `=`, found '\\''"),
+            ("command =", "expected a value, found end of file"),
+            ("command = 'a' 'b'", "expected end of line, found '\\''"),
+            ("command = nope", "invalid value `nope`"),
+            ("command = [1 2]", "expected `,` or `]`, found '2'"),
+            (
+                "env = { A = '1' B = '2' }",
+                "expected `,` or `}`, found 'B'",
+            ),
+            ("command = \"a", "unterminated string"),
+            ("command = \"a\nb\"", "unterminated string"),
+            ("command = 'a", "expected `'`, found end of file"),
+            (
+                "command = \"\"\"a\"\"\"",
+                "multi-line strings are not supported",
+            ),
+            ("command = \"\\q\"", "invalid escape `\\q`"),
+            ("command = \"\\uZZZZ\"", "invalid unicode escape `\\uZZZZ`"),
+            (
+                "command = \"\\UD800D800\"",
+                "invalid unicode escape `\\UD800D800`",
+            ),
+            ("[process.web", "expected `]`, found end of file"),
+        ];
+        for (src, err) in cases {
+            assert_eq!(error(src), (1, err.to_string()), "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn duplicates() {
+        let cases = [
+            (
+                "[process.web]\n[process.web]",
+                2,
+                "duplicate table `process.web`",
+            ),
+            ("cwd = 'a'\ncwd = 'b'", 2, "duplicate key `cwd`"),
+            ("env = { A = '1' }\nenv.A = '2'", 2, "duplicate key `env.A`"),
+            (
+                "[process.web]\ncwd = 'a'\ncwd = 'b'",
+                3,
+                "duplicate key `process.web.cwd`",
+            ),
+        ];
+        for (src, line, err) in cases {
+            assert_eq!(error(src), (line, err.to_string()), "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn value_errors() {
+        let cases = [
+            ("nope = 'a'", "unknown key `nope`"),
+            ("process.web.nope = 'a'", "unknown key `process.web.nope`"),
+            ("cwd = { a = 'b' }", "unknown key `cwd.a`"),
+            ("cwd = 1_000", "expected a string, found an integer"),
+            ("cwd = -1.5e3", "expected a string, found a float"),
+            ("cwd = true", "expected a string, found a boolean"),
+            ("cwd = []", "expected a string, found an array"),
+            (
+                "command = 1",
+                "expected an array of strings, found an integer",
+            ),
+            ("watch = ['a', 1]", "expected a string, found an integer"),
+            (
+                "restart = 'sometimes'",
+                "invalid restart policy `sometimes`",
+            ),
+        ];
+        for (src, err) in cases {
+            assert_eq!(error(src), (1, err.to_string()), "{:?}", src);
+        }
+        for src in ["ready = 'ftp:1'", "listen = ['udp:1']", "proxy = ['8000']"] {
+            assert_eq!(error(src).0, 1, "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn line_numbers() {
+        assert_eq!(error("\n# comment\n\ncwd = 1").0, 4);
+        assert_eq!(error("ignore = [\n  'a',\n  'b',\n]\nnope = 1").0, 5);
+        // Values are checked against the line their key is on.
+        assert_eq!(error("ignore = [\n  'a',\n  1,\n]").0, 1);
+        assert_eq!(error("ignore = [\n  'a',\n  b,\n]").0, 3);
+        assert_eq!(error("cwd = 'a'\n\ncwd = \"\\q\"").0, 3);
+    }
+
+    #[test]
+    fn command_or_processes() {
+        let err = (4, "set either `command` or processes, not both".to_string());
+        assert_eq!(error("command = 'a'\n\n[process.web]\ncommand = 'b'"), err);
+        assert_eq!(
+            error("command = 'a'\nprocfile = 'Procfile'").1,
+            "set either `command` or processes, not both"
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::mpsc::{channel, Sender},
+    thread,
+};
+
+use anyhow::{anyhow, bail};
+
+/// A request sent over the control socket, one per line. Restart and quit
+/// act on the named process or all of them.
+pub(crate) enum Request {
+    /// Answered once restarted, or once ready if `wait`.
+    Restart {
+        name: Option<String>,
+        wait: bool,
+    },
+    Quit(Option<String>),
+    Status,
+    /// The last `lines` lines of output, then new lines as they arrive if
+    /// `follow`.
+    Logs {
+        lines: usize,
+        follow: bool,
+    },
+}
+
+impl FromStr for Request {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        match words.as_slice() {
+            ["restart", rest @ ..] => {
+                let (wait, rest) = match rest {
+                    ["--wait", rest @ ..] => (true, rest),
+                    rest => (false, rest),
+                };
+                match rest {
+                    [] => Ok(Request::Restart { name: None, wait }),
+                    [name] => Ok(Request::Restart {
+                        name: Some(name.to_string()),
+                        wait,
+                    }),
+                    _ => Err(format!("unknown request {:?}", s)),
+                }
+            }
+            ["quit"] => Ok(Request::Quit(None)),
+            ["quit", name] => Ok(Request::Quit(Some(name.to_string()))),
+            ["status"] => Ok(Request::Status),
+            ["logs", lines] | ["logs", lines, "follow"] => Ok(Request::Logs {
+                lines: lines
+                    .parse()
+                    .map_err(|_| format!("invalid line count {:?}", lines))?,
+                follow: words.len() == 3,
+            }),
+            _ => Err(format!("unknown request {:?}", s)),
+        }
+    }
+}
+
+/// Where a request came from, answered once it has been handled.
+///
+/// A socket response is any number of data lines followed by `ok` or
+/// `error <msg>`.
+pub(crate) enum Reply {
+    Socket(UnixStream),
+    Channel(Sender<Result<(), String>>),
+}
+
+impl Reply {
+    pub fn send(self, result: Result<(), String>) {
+        match self {
+            Reply::Socket(mut stream) => {
+                let line = match result {
+                    Ok(()) => "ok\n".to_string(),
+                    Err(e) => format!("error {}\n", e),
+                };
+                let _ = stream.write_all(line.as_bytes());
+            }
+            Reply::Channel(tx) => {
+                let _ = tx.send(result);
+            }
+        }
+    }
+
+    /// Split into `n` replies, answering this one once all of them have
+    /// been, with the first error if any.
+    pub fn split(self, n: usize) -> Vec<Reply> {
+        if n == 1 {
+            return vec![self];
+        }
+        let (tx, rx) = channel();
+        let replies = (0..n).map(|_| Reply::Channel(tx.clone())).collect();
+        thread::spawn(move || {
+            let mut result = Ok(());
+            for res in rx.iter().take(n) {
+                if result.is_ok() {
+                    result = res;
+                }
+            }
+            self.send(result);
+        });
+        replies
+    }
+
+    /// Answer successfully with data `lines`.
+    pub fn send_lines(mut self, lines: &[String]) {
+        if let Reply::Socket(stream) = &mut self {
+            for line in lines {
+                if writeln!(stream, "{}", line).is_err() {
+                    return;
+                }
+            }
+        }
+        self.send(Ok(()));
+    }
+}
+
+/// The control socket path beside the control file `rstrtr`.
+pub fn socket_path(rstrtr: &Path) -> PathBuf {
+    let mut path = OsString::from(rstrtr);
+    path.push(".sock");
+    path.into()
+}
+
+/// Listen on the control socket at `path`, calling `handler` from a
+/// background thread for each request received.
+pub(crate) fn listen<F>(path: &Path, handler: F) -> anyhow::Result<()>
+where
+    F: Fn(Request, Reply) + Clone + Send + 'static,
+{
+    if UnixStream::connect(path).is_ok() {
+        bail!("Another rstrtr is listening on {}", path.display());
+    }
+    let _ = fs::remove_file(path);
+    let listener = UnixListener::bind(path)?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let handler = handler.clone();
+            thread::spawn(move || {
+                let mut line = String::new();
+                let mut reader = BufReader::new(&stream);
+                if reader.read_line(&mut line).is_err() {
+                    return;
+                }
+                match line.parse() {
+                    Ok(request) => handler(request, Reply::Socket(stream)),
+                    Err(e) => Reply::Socket(stream).send(Err(e)),
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Send `request` to the supervisor listening on `path` and wait for it to
+/// be handled, returning the response's data lines or `None` if no
+/// supervisor is listening.
+pub fn request(path: &Path, request: &str) -> anyhow::Result<Option<Vec<String>>> {
+    let mut lines = Vec::new();
+    Ok(stream(path, request, |line| lines.push(line))?.map(|()| lines))
+}
+
+/// Like [`request`], but calling `on_line` with each data line as it
+/// arrives.
+pub fn stream<F>(path: &Path, request: &str, mut on_line: F) -> anyhow::Result<Option<()>>
+where
+    F: FnMut(String),
+{
+    let mut stream = match UnixStream::connect(path) {
+        Ok(stream) => stream,
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+            ) =>
+        {
+            return Ok(None)
+        }
+        Err(e) => return Err(e.into()),
+    };
+    writeln!(stream, "{}", request)?;
+
+    for line in BufReader::new(&stream).lines() {
+        let line = line?;
+        if line == "ok" {
+            return Ok(Some(()));
+        }
+        if let Some(e) = line.strip_prefix("error ") {
+            return Err(anyhow!("{}", e));
+        }
+        on_line(line);
+    }
+    bail!("rstrtr closed the control socket without responding");
+}
@@ -0,0 +1,289 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::glob::Glob;
+
+/// A gitignore style pattern.
+///
+/// Patterns without a `/` match any path component, patterns with a
+/// leading or inner `/` match from the watched root. A trailing `/` only
+/// matches directories and a leading `!` negates the pattern.
+struct Pattern {
+    glob: Glob,
+    anchored: bool,
+    dir_only: bool,
+    negated: bool,
+}
+
+impl Pattern {
+    /// Does this pattern match `rel` or one of its parent directories.
+    fn matches(&self, root: &Path, rel: &[&str]) -> bool {
+        (1..=rel.len()).any(|end| {
+            if self.dir_only && end == rel.len() && !root.join(rel.join("/")).is_dir() {
+                return false;
+            }
+            if self.anchored {
+                self.glob.matches(&rel[..end].join("/"))
+            } else {
+                self.glob.matches(rel[end - 1])
+            }
+        })
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negated, s) = match s.strip_prefix('!') {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+        let (dir_only, s) = match s.strip_suffix('/') {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+        let anchored = s.contains('/');
+        let s = s.strip_prefix('/').unwrap_or(s);
+        Ok(Self {
+            glob: s.parse()?,
+            anchored,
+            dir_only,
+            negated,
+        })
+    }
+}
+
+struct Root {
+    path: PathBuf,
+    rules: Vec<Pattern>,
+}
+
+/// Decides which changes in watched paths should restart the command.
+pub struct Filter {
+    roots: Vec<Root>,
+    ignore: Vec<Pattern>,
+    include: Vec<Pattern>,
+    paths: Vec<PathBuf>,
+}
+
+impl Filter {
+    /// Build a filter for the canonical watched `roots`, honoring their
+    /// `.gitignore` and `.ignore` files when `vcs_ignore` is set.
+    pub fn new(
+        roots: &[PathBuf],
+        ignore: &[String],
+        include: &[String],
+        vcs_ignore: bool,
+    ) -> anyhow::Result<Self> {
+        let roots = roots
+            .iter()
+            .map(|path| Root {
+                path: path.clone(),
+                rules: if vcs_ignore {
+                    read_ignore_files(path)
+                } else {
+                    Vec::new()
+                },
+            })
+            .collect();
+        Ok(Self {
+            roots,
+            ignore: ignore.iter().map(|s| s.parse()).collect::<Result<_, _>>()?,
+            include: include
+                .iter()
+                .map(|s| s.parse())
+                .collect::<Result<_, _>>()?,
+            paths: Vec::new(),
+        })
+    }
+
+    /// Always ignore changes to `path`, used for rstrtr's own files.
+    pub fn ignore_path(&mut self, path: PathBuf) {
+        self.paths.push(path);
+    }
+
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        if self.paths.iter().any(|p| p == path) {
+            return true;
+        }
+        let root = self
+            .roots
+            .iter()
+            .filter(|root| path.starts_with(&root.path))
+            .max_by_key(|root| root.path.as_os_str().len());
+        let (root_path, rel) = match root {
+            Some(root) => (root.path.as_path(), path.strip_prefix(&root.path).unwrap()),
+            None => (Path::new("/"), path),
+        };
+        let rel: Vec<&str> = rel
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(c) => c.to_str(),
+                _ => None,
+            })
+            .collect();
+        if rel.is_empty() {
+            return false;
+        }
+
+        let mut ignored = false;
+        for rule in root.map(|root| root.rules.as_slice()).unwrap_or_default() {
+            if rule.matches(root_path, &rel) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+            || self.ignore.iter().any(|p| p.matches(root_path, &rel))
+            || (!self.include.is_empty()
+                && !self.include.iter().any(|p| p.matches(root_path, &rel)))
+    }
+}
+
+fn read_ignore_files(root: &Path) -> Vec<Pattern> {
+    let mut rules = vec![".git/".parse().unwrap()];
+    for name in [".gitignore", ".ignore"] {
+        let contents = match fs::read_to_string(root.join(name)) {
+            Ok(contents) => contents,
+            Err(_) => continue,
+        };
+        rules.extend(
+            contents
+                .lines()
+                .map(str::trim_end)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .filter_map(|line| line.parse().ok()),
+        );
+    }
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    /// A directory of files for a test, removed afterwards.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str, files: &[(&str, &str)]) -> Self {
+            let dir = env::temp_dir().join(format!("rstrtr-test-{}-{}", std::process::id(), name));
+            let _ = fs::remove_dir_all(&dir);
+            for (path, contents) in files {
+                let path = dir.join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, contents).unwrap();
+            }
+            Self(dir.canonicalize().unwrap())
+        }
+
+        fn filter(&self, ignore: &[&str], include: &[&str], vcs_ignore: bool) -> Filter {
+            let strings = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+            Filter::new(
+                std::slice::from_ref(&self.0),
+                &strings(ignore),
+                &strings(include),
+                vcs_ignore,
+            )
+            .unwrap()
+        }
+
+        fn ignored(&self, filter: &Filter, path: &str) -> bool {
+            filter.is_ignored(&self.0.join(path))
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const GITIGNORE: &str = "\
+# comment
+target/
+*.log
+!keep.log
+/top-only
+build/
+";
+
+    #[test]
+    fn gitignore() {
+        let dir = TempDir::new(
+            "gitignore",
+            &[
+                (".gitignore", GITIGNORE),
+                ("target/debug/rstrtr", ""),
+                ("src/main.rs", ""),
+                ("build", ""),
+                ("a.log", ""),
+                ("keep.log", ""),
+                ("sub/b.log", ""),
+                ("top-only", ""),
+                ("sub/top-only", ""),
+            ],
+        );
+        let filter = dir.filter(&[], &[], true);
+        assert!(dir.ignored(&filter, "target/debug/rstrtr"));
+        assert!(dir.ignored(&filter, "target"));
+        assert!(!dir.ignored(&filter, "src/main.rs"));
+        // Only directories match a trailing `/`.
+        assert!(!dir.ignored(&filter, "build"));
+        assert!(dir.ignored(&filter, "a.log"));
+        assert!(dir.ignored(&filter, "sub/b.log"));
+        // A later negated pattern wins.
+        assert!(!dir.ignored(&filter, "keep.log"));
+        // A leading `/` anchors to the root.
+        assert!(dir.ignored(&filter, "top-only"));
+        assert!(!dir.ignored(&filter, "sub/top-only"));
+        assert!(dir.ignored(&filter, ".git/HEAD"));
+        assert!(!filter.is_ignored(&dir.0));
+
+        let filter = dir.filter(&[], &[], false);
+        assert!(!dir.ignored(&filter, "a.log"));
+        assert!(!dir.ignored(&filter, "target/debug/rstrtr"));
+    }
+
+    #[test]
+    fn ignore_and_include() {
+        let dir = TempDir::new(
+            "include",
+            &[
+                (".gitignore", "target/\n"),
+                ("target/gen.rs", ""),
+                ("src/main.rs", ""),
+            ],
+        );
+        let filter = dir.filter(&["*.tmp", "docs/**", "src/gen.rs"], &[], true);
+        assert!(dir.ignored(&filter, "x.tmp"));
+        assert!(dir.ignored(&filter, "src/x.tmp"));
+        assert!(dir.ignored(&filter, "docs/a/b.md"));
+        assert!(!dir.ignored(&filter, "src/docs/b.md"));
+        assert!(!dir.ignored(&filter, "src/main.rs"));
+
+        let filter = dir.filter(&["src/gen.rs"], &["*.rs"], true);
+        assert!(!dir.ignored(&filter, "src/main.rs"));
+        assert!(dir.ignored(&filter, "README.md"));
+        // Ignoring takes precedence over including.
+        assert!(dir.ignored(&filter, "src/gen.rs"));
+        assert!(dir.ignored(&filter, "target/gen.rs"));
+    }
+
+    #[test]
+    fn paths() {
+        let dir = TempDir::new("paths", &[("src/main.rs", "")]);
+        let mut filter = dir.filter(&["*.tmp"], &[], true);
+        filter.ignore_path(dir.0.join(".rstrtr"));
+        assert!(dir.ignored(&filter, ".rstrtr"));
+        assert!(!dir.ignored(&filter, "sub/.rstrtr"));
+        // Paths outside the watched roots are still matched by name.
+        assert!(filter.is_ignored(Path::new("/elsewhere/x.tmp")));
+        assert!(!filter.is_ignored(Path::new("/elsewhere/x.rs")));
+    }
+}
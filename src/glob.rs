@@ -0,0 +1,189 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+
+enum Token {
+    Char(char),
+    Any,
+    Star,
+    DoubleStar,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+/// A shell style glob supporting `?`, `*`, `**` and `[...]` classes.
+///
+/// `*` and `?` never match `/`, `**` matches across directories.
+pub struct Glob {
+    tokens: Vec<Token>,
+}
+
+impl Glob {
+    pub fn matches(&self, s: &str) -> bool {
+        let chars: Vec<char> = s.chars().collect();
+        match_from(&self.tokens, &chars)
+    }
+}
+
+fn match_from(tokens: &[Token], s: &[char]) -> bool {
+    let (token, rest) = match tokens.split_first() {
+        Some(split) => split,
+        None => return s.is_empty(),
+    };
+    match token {
+        Token::Char(c) => s.first() == Some(c) && match_from(rest, &s[1..]),
+        Token::Any => s.first().is_some_and(|c| *c != '/') && match_from(rest, &s[1..]),
+        Token::Class { negated, ranges } => {
+            s.first().is_some_and(|c| {
+                *c != '/' && ranges.iter().any(|(lo, hi)| lo <= c && c <= hi) != *negated
+            }) && match_from(rest, &s[1..])
+        }
+        Token::Star => {
+            for i in 0..=s.len() {
+                if match_from(rest, &s[i..]) {
+                    return true;
+                }
+                if s.get(i) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Token::DoubleStar => {
+            if let Some((Token::Char('/'), after)) = rest.split_first() {
+                if match_from(after, s) {
+                    return true;
+                }
+            }
+            (0..=s.len()).any(|i| match_from(rest, &s[i..]))
+        }
+    }
+}
+
+impl FromStr for Glob {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = Vec::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '?' => Token::Any,
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    Token::DoubleStar
+                }
+                '*' => Token::Star,
+                '\\' => Token::Char(
+                    chars
+                        .next()
+                        .ok_or_else(|| anyhow!("trailing \\ in glob {:?}", s))?,
+                ),
+                '[' => {
+                    let negated = matches!(chars.peek(), Some('!') | Some('^'));
+                    if negated {
+                        chars.next();
+                    }
+                    let mut ranges = Vec::new();
+                    loop {
+                        let lo = match chars.next() {
+                            Some(']') if !ranges.is_empty() => break,
+                            Some('\\') => chars.next(),
+                            lo => lo,
+                        }
+                        .ok_or_else(|| anyhow!("unclosed [ in glob {:?}", s))?;
+                        let mut hi = lo;
+                        if chars.peek() == Some(&'-') {
+                            chars.next();
+                            match chars.next() {
+                                Some(']') => {
+                                    ranges.push((lo, lo));
+                                    ranges.push(('-', '-'));
+                                    break;
+                                }
+                                Some(c) => hi = c,
+                                None => return Err(anyhow!("unclosed [ in glob {:?}", s)),
+                            }
+                        }
+                        ranges.push((lo, hi));
+                    }
+                    Token::Class { negated, ranges }
+                }
+                c => Token::Char(c),
+            };
+            tokens.push(token);
+        }
+        Ok(Self { tokens })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(glob: &str, s: &str) -> bool {
+        glob.parse::<Glob>().unwrap().matches(s)
+    }
+
+    #[test]
+    fn literals() {
+        assert!(matches("main.rs", "main.rs"));
+        assert!(!matches("main.rs", "main.rsx"));
+        assert!(!matches("main.rs", "src/main.rs"));
+        assert!(matches("\\*\\?", "*?"));
+        assert!(!matches("\\*", "a"));
+        assert!(matches("", ""));
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*.rs", ".rs"));
+        assert!(!matches("*.rs", "src/main.rs"));
+        assert!(matches("src/*", "src/main.rs"));
+        assert!(!matches("src/*", "src/bin/main.rs"));
+        assert!(matches("a*b*c", "abbbc"));
+        assert!(matches("?.rs", "a.rs"));
+        assert!(!matches("?.rs", "ab.rs"));
+        assert!(!matches("a?b", "a/b"));
+    }
+
+    #[test]
+    fn double_star() {
+        assert!(matches("**", "a/b/c"));
+        assert!(matches("**/main.rs", "main.rs"));
+        assert!(matches("**/main.rs", "src/bin/main.rs"));
+        assert!(matches("src/**", "src/bin/main.rs"));
+        assert!(!matches("src/**", "tests/main.rs"));
+        assert!(matches("a/**/b", "a/b"));
+        assert!(matches("a/**/b", "a/x/y/b"));
+        assert!(!matches("a/**/b", "a/x/y/c"));
+        assert!(matches("**.rs", "src/main.rs"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("[abc].rs", "b.rs"));
+        assert!(!matches("[abc].rs", "d.rs"));
+        assert!(matches("file[0-9]", "file7"));
+        assert!(!matches("file[0-9]", "filex"));
+        assert!(matches("[!a]", "b"));
+        assert!(!matches("[!a]", "a"));
+        assert!(matches("[^a]", "b"));
+        assert!(!matches("[!a]", "/"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("[\\]]", "]"));
+        assert!(matches("[a-cx-z]", "y"));
+    }
+
+    #[test]
+    fn errors() {
+        assert!("a\\".parse::<Glob>().is_err());
+        assert!("[abc".parse::<Glob>().is_err());
+        assert!("[a-".parse::<Glob>().is_err());
+        assert!("[".parse::<Glob>().is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    path::PathBuf,
+    process::ExitStatus,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    control::{Reply, Request},
+    signal::Signal,
+    status::Status,
+    supervisor::Event,
+};
+
+#[derive(Clone, Debug)]
+/// Something that happened to the supervised command.
+pub enum Lifecycle {
+    Started {
+        name: String,
+        pid: u32,
+    },
+    /// The process passed its readiness probe.
+    Ready {
+        name: String,
+        pid: u32,
+    },
+    /// The process failed its liveness probe and is restarted.
+    Unhealthy {
+        name: String,
+        pid: u32,
+        reason: String,
+    },
+    Changed(PathBuf),
+    BuildFailed(String),
+    Stopping {
+        name: String,
+        pid: u32,
+        signal: Signal,
+    },
+    Exited {
+        name: String,
+        pid: u32,
+        status: ExitStatus,
+    },
+    Restarting {
+        name: String,
+        delay: Duration,
+    },
+    Quit {
+        code: i32,
+    },
+}
+
+pub(crate) type Subscribers = Arc<Mutex<Vec<Sender<Lifecycle>>>>;
+
+#[derive(Clone)]
+/// Controls a [`Supervisor`](crate::Supervisor) from other threads.
+pub struct Handle {
+    pub(crate) tx: Sender<Event>,
+    pub(crate) status: Arc<Mutex<Vec<Status>>>,
+    pub(crate) subscribers: Subscribers,
+}
+
+impl Handle {
+    /// Build and restart every process, waiting until they are running
+    /// again.
+    pub fn restart(&self) -> anyhow::Result<()> {
+        self.request(Request::Restart {
+            name: None,
+            wait: false,
+        })
+    }
+
+    /// Like [`Handle::restart`], but waiting until every process is ready
+    /// and failing if one isn't within the ready timeout.
+    pub fn restart_and_wait(&self) -> anyhow::Result<()> {
+        self.request(Request::Restart {
+            name: None,
+            wait: true,
+        })
+    }
+
+    /// Build and restart the process `name`, waiting until it is running
+    /// again.
+    pub fn restart_process(&self, name: &str) -> anyhow::Result<()> {
+        self.request(Request::Restart {
+            name: Some(name.to_string()),
+            wait: false,
+        })
+    }
+
+    /// Like [`Handle::restart_process`], but waiting until it is ready and
+    /// failing if it isn't within the ready timeout.
+    pub fn restart_process_and_wait(&self, name: &str) -> anyhow::Result<()> {
+        self.request(Request::Restart {
+            name: Some(name.to_string()),
+            wait: true,
+        })
+    }
+
+    /// Stop every process, waiting until the supervisor has quit.
+    pub fn quit(&self) -> anyhow::Result<()> {
+        self.request(Request::Quit(None))
+    }
+
+    /// Stop the process `name` without restarting it, waiting until it has
+    /// exited.
+    pub fn quit_process(&self, name: &str) -> anyhow::Result<()> {
+        self.request(Request::Quit(Some(name.to_string())))
+    }
+
+    /// The status of each process.
+    pub fn status(&self) -> Vec<Status> {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Receive every [`Lifecycle`] event from now on.
+    pub fn subscribe(&self) -> Receiver<Lifecycle> {
+        let (tx, rx) = channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    fn request(&self, request: Request) -> anyhow::Result<()> {
+        let (tx, rx) = channel();
+        self.tx
+            .send(Event::Request(request, Reply::Channel(tx)))
+            .map_err(|_| anyhow!("Supervisor has quit"))?;
+        rx.recv()
+            .map_err(|_| anyhow!("Supervisor has quit"))?
+            .map_err(|e| anyhow!(e))
+    }
+}
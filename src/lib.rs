@@ -0,0 +1,49 @@
+//! Run commands and restart them when they exit, when asked to over a
+//! control file or socket, or when watched files change.
+//!
+//! ```no_run
+//! use rstrtr::{RestartPolicy, Supervisor};
+//!
+//! let supervisor = Supervisor::builder(["cargo", "run"])
+//!     .restart_policy(RestartPolicy::OnFailure)
+//!     .control_path("./.rstrtr")
+//!     .build()?;
+//! let handle = supervisor.handle();
+//! let events = handle.subscribe();
+//! std::thread::spawn(move || supervisor.run());
+//!
+//! handle.restart()?;
+//! for event in events {
+//!     println!("{:?}", event);
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+mod backoff;
+pub mod control;
+mod filter;
+mod glob;
+mod handle;
+mod listen;
+mod lock;
+mod log_file;
+mod output;
+mod probe;
+mod process;
+mod proxy;
+mod regex;
+mod sd_notify;
+mod signal;
+mod status;
+mod supervisor;
+
+pub use handle::{Handle, Lifecycle};
+pub use listen::Listen;
+pub use output::{ColorMode, OutputMode};
+pub use probe::Probe;
+pub use process::Process;
+pub use proxy::Proxy;
+pub use regex::Regex;
+pub use signal::Signal;
+pub use status::Status;
+pub use supervisor::{RestartPolicy, Supervisor, SupervisorBuilder};
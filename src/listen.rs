@@ -0,0 +1,88 @@
+use std::{
+    fs, io,
+    net::TcpListener,
+    os::unix::{
+        io::{OwnedFd, RawFd},
+        net::UnixListener,
+    },
+    path::PathBuf,
+    str::FromStr,
+};
+
+use anyhow::anyhow;
+
+/// The first file descriptor passed by socket activation.
+const LISTEN_FDS_START: RawFd = 3;
+
+/// A socket bound once by rstrtr and passed to each process it starts, as
+/// systemd's socket activation does, so connections queue across restarts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Listen {
+    /// Listen on `host:port`.
+    Tcp(String),
+    /// Listen on a Unix socket at the path, replacing any stale one.
+    Unix(PathBuf),
+}
+
+impl FromStr for Listen {
+    type Err = anyhow::Error;
+
+    /// Parse `tcp:[HOST:]PORT` or `unix:PATH`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(addr) = s.strip_prefix("tcp:") {
+            return Ok(Listen::Tcp(match addr.contains(':') {
+                true => addr.to_string(),
+                false => format!("0.0.0.0:{}", addr),
+            }));
+        }
+        match s.strip_prefix("unix:") {
+            Some(path) if !path.is_empty() => Ok(Listen::Unix(path.into())),
+            _ => Err(anyhow!(
+                "invalid listen address {:?}, expected tcp:[HOST:]PORT or unix:PATH",
+                s
+            )),
+        }
+    }
+}
+
+impl Listen {
+    pub(crate) fn bind(&self) -> anyhow::Result<OwnedFd> {
+        let res = match self {
+            Listen::Tcp(addr) => TcpListener::bind(addr).map(OwnedFd::from),
+            Listen::Unix(path) => {
+                let _ = fs::remove_file(path);
+                UnixListener::bind(path).map(OwnedFd::from)
+            }
+        };
+        res.map_err(|e| match self {
+            Listen::Tcp(addr) => anyhow!("Error listening on {}: {}", addr, e),
+            Listen::Unix(path) => anyhow!("Error listening on {}: {}", path.display(), e),
+        })
+    }
+}
+
+/// Move `fds` to where socket activation expects them, from file
+/// descriptor 3 on, leaving them open across exec. Only async-signal-safe
+/// calls are made, for use after forking.
+pub(crate) fn pass(fds: &[RawFd]) -> io::Result<()> {
+    let end = LISTEN_FDS_START + fds.len() as RawFd;
+    // Duplicate everything above the target range first, so moving one
+    // into place can't replace another.
+    let mut moved = [0; 64];
+    if fds.len() > moved.len() {
+        return Err(io::Error::from_raw_os_error(libc::EMFILE));
+    }
+    for (fd, moved) in fds.iter().zip(&mut moved) {
+        *moved = unsafe { libc::fcntl(*fd, libc::F_DUPFD_CLOEXEC, end) };
+        if *moved < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    for (i, fd) in moved[..fds.len()].iter().enumerate() {
+        // dup2 clears close-on-exec on the new descriptor.
+        if unsafe { libc::dup2(*fd, LISTEN_FDS_START + i as RawFd) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use std::{
+    ffi::OsString,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    process,
+};
+
+use anyhow::bail;
+
+/// An exclusive lock beside the control file, held while rstrtr runs and
+/// containing its pid.
+pub struct Lock {
+    path: PathBuf,
+    file: File,
+}
+
+impl Lock {
+    /// Take the lock for the control file `rstrtr`, also returning the pid
+    /// of a crashed rstrtr that left a stale lock behind.
+    pub fn acquire(rstrtr: &Path) -> anyhow::Result<(Self, Option<u32>)> {
+        let path = lock_path(rstrtr);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let pid = contents.trim().parse().ok();
+
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == -1 {
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::WouldBlock {
+                return Err(e.into());
+            }
+            match pid {
+                Some(pid) => bail!(
+                    "Another rstrtr (pid {}) is running for {}",
+                    pid,
+                    rstrtr.display()
+                ),
+                None => bail!("Another rstrtr is running for {}", rstrtr.display()),
+            }
+        }
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        writeln!(file, "{}", process::id())?;
+        Ok((Self { path, file }, pid))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Lock {
+    /// Clear the pid rather than removing the file, another rstrtr may have
+    /// opened it already and would lock the removed file while a third
+    /// locks a new one at the same path.
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+    }
+}
+
+fn lock_path(rstrtr: &Path) -> PathBuf {
+    let mut path = OsString::from(rstrtr);
+    path.push(".lock");
+    path.into()
+}
@@ -0,0 +1,163 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// A log file rotated to `<path>.1`, `<path>.2` and so on once it would
+/// grow past `max_size` bytes, keeping at most `max_files` old logs.
+pub(crate) struct LogFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    pub fn open(path: &Path, max_size: u64, max_files: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_size,
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.max_files).rev() {
+                let from = rotated(&self.path, n);
+                if from.exists() {
+                    fs::rename(from, rotated(&self.path, n + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated(&self.path, 1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Where the `n`th old log of `path` is kept.
+pub(crate) fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(format!(".{}", n));
+    path.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("rstrtr-test-{}-{}", std::process::id(), name));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn log(&self) -> PathBuf {
+            self.0.join("log")
+        }
+
+        /// The contents of the log and its rotations, stopping at the first
+        /// missing one.
+        fn contents(&self) -> Vec<String> {
+            let rotations = (1..).map_while(|n| fs::read_to_string(rotated(&self.log(), n)).ok());
+            let mut contents = vec![fs::read_to_string(self.log()).unwrap()];
+            contents.extend(rotations);
+            contents
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn rotation() {
+        let dir = TempDir::new("log-rotation");
+        let mut log = LogFile::open(&dir.log(), 10, 2).unwrap();
+        log.write_line("1111").unwrap();
+        log.write_line("2222").unwrap();
+        assert_eq!(dir.contents(), ["1111\n2222\n"]);
+
+        log.write_line("3333").unwrap();
+        assert_eq!(dir.contents(), ["3333\n", "1111\n2222\n"]);
+
+        log.write_line("4444").unwrap();
+        log.write_line("5555").unwrap();
+        assert_eq!(dir.contents(), ["5555\n", "3333\n4444\n", "1111\n2222\n"]);
+
+        // The oldest is dropped once there are `max_files`.
+        log.write_line("6666").unwrap();
+        log.write_line("7777").unwrap();
+        assert_eq!(dir.contents(), ["7777\n", "5555\n6666\n", "3333\n4444\n"]);
+    }
+
+    #[test]
+    fn long_lines() {
+        let dir = TempDir::new("log-long-lines");
+        let mut log = LogFile::open(&dir.log(), 10, 2).unwrap();
+        // Too long for any log, but an empty one isn't rotated for it.
+        log.write_line("0123456789").unwrap();
+        assert_eq!(dir.contents(), ["0123456789\n"]);
+
+        log.write_line("1").unwrap();
+        assert_eq!(dir.contents(), ["1\n", "0123456789\n"]);
+    }
+
+    #[test]
+    fn existing() {
+        let dir = TempDir::new("log-existing");
+        fs::write(dir.log(), "old\n").unwrap();
+        let mut log = LogFile::open(&dir.log(), 10, 2).unwrap();
+        log.write_line("1111").unwrap();
+        assert_eq!(dir.contents(), ["old\n1111\n"]);
+
+        log.write_line("2222").unwrap();
+        assert_eq!(dir.contents(), ["2222\n", "old\n1111\n"]);
+    }
+
+    #[test]
+    fn no_old_logs() {
+        let dir = TempDir::new("log-no-old-logs");
+        let mut log = LogFile::open(&dir.log(), 10, 0).unwrap();
+        log.write_line("1111").unwrap();
+        log.write_line("2222").unwrap();
+        log.write_line("3333").unwrap();
+        assert_eq!(dir.contents(), ["3333\n"]);
+
+        log.write_line("4444").unwrap();
+        log.write_line("5555").unwrap();
+        assert_eq!(dir.contents(), ["5555\n"]);
+    }
+}
@@ -2,21 +2,35 @@ use std::{
     collections::hash_map::DefaultHasher,
     env,
     hash::{Hash, Hasher},
-    path::PathBuf,
-    process::Command,
-    sync::mpsc::channel,
+    io,
+    path::{Path, PathBuf},
     time::Duration,
 };
 
+use anyhow::{anyhow, bail};
 use clap::Parser;
-use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use rstrtr::{
+    control, ColorMode, Listen, OutputMode, Probe, Process, Proxy, RestartPolicy, Signal, Status,
+    Supervisor, SupervisorBuilder,
+};
+
+mod config;
+mod procfile;
+
+use config::{Config, ProcessConfig};
 
 #[derive(Parser)]
 #[clap(author, about, version)]
 struct Flags {
-    #[clap(short, long, default_value = "./.rstrtr")]
-    /// Change control file path.
-    rstrtr: PathBuf,
+    #[clap(short, long)]
+    /// Change control file path [default: ./.rstrtr, beside rstrtr.toml if
+    /// there is one].
+    rstrtr: Option<PathBuf>,
+
+    #[clap(short, long)]
+    /// Project config file [default: rstrtr.toml in the current directory
+    /// or its nearest parent containing one].
+    config: Option<PathBuf>,
 
     #[clap(short, long)]
     /// Use a control file in a tmp dir.
@@ -30,103 +44,593 @@ struct Flags {
 #[derive(Parser)]
 enum Subcommand {
     /// Run & restart command upon it exiting.
-    Run {
-        #[clap(required = true)]
-        /// The command to run. Separate with -- if required.
-        command: Vec<String>,
-    },
+    Run(Box<RunArgs>),
     /// Instruct rstrtr ill and restart command.
-    Restart,
+    Restart {
+        #[clap(long)]
+        /// Wait until the restarted command is ready, failing if it isn't
+        /// within rstrtr's --ready-timeout.
+        wait: bool,
+
+        /// Only restart this process.
+        process: Option<String>,
+    },
     /// Instruct rstrtr to kill the command and quit.
-    Quit,
+    Quit {
+        /// Only stop this process, rstrtr keeps running the others.
+        process: Option<String>,
+    },
+    /// Print recent output of the command run by rstrtr. Only rstrtr's
+    /// messages are kept for raw output unless there is a log file.
+    Logs {
+        #[clap(short, long)]
+        /// Keep printing output as it arrives, until rstrtr quits.
+        follow: bool,
+
+        #[clap(short = 'n', long, default_value = "10")]
+        /// Number of recent lines to print.
+        lines: usize,
+    },
+    /// Show the state of the command run by rstrtr.
+    Status {
+        #[clap(long)]
+        /// Print the status as JSON, an array of each process's status if
+        /// there are several.
+        json: bool,
+    },
+}
+
+#[derive(Parser)]
+struct RunArgs {
+    #[clap(long, arg_enum)]
+    /// When to restart the command after it exits by itself, unless-stopped
+    /// is an alias of always [default: always].
+    restart: Option<RestartPolicy>,
+
+    #[clap(long)]
+    /// Give up after the command exits this many times within the restart
+    /// window.
+    max_restarts: Option<usize>,
+
+    #[clap(long, default_value = "60s", parse(try_from_str = parse_duration))]
+    /// Window in which --max-restarts are counted.
+    restart_window: Duration,
+
+    #[clap(long, multiple_occurrences(true), number_of_values(1))]
+    /// Restart the command when files under this path change, may be
+    /// repeated.
+    watch: Vec<PathBuf>,
+
+    #[clap(long, multiple_occurrences(true), number_of_values(1))]
+    /// Ignore changes to paths matching this glob, may be repeated.
+    ignore: Vec<String>,
+
+    #[clap(long, multiple_occurrences(true), number_of_values(1))]
+    /// Only restart for changes to paths matching this glob, may be repeated.
+    include: Vec<String>,
+
+    #[clap(long)]
+    /// Don't honor .gitignore and .ignore files in watched paths.
+    no_vcs_ignore: bool,
+
+    #[clap(long)]
+    /// Shell command to build the command before a requested restart, the
+    /// running command is kept if it fails.
+    build: Option<String>,
+
+    #[clap(long, default_value = "100ms", parse(try_from_str = parse_duration))]
+    /// Delay before restarting a command that exited by itself.
+    backoff_initial: Duration,
+
+    #[clap(long, default_value = "2", parse(try_from_str = parse_multiplier))]
+    /// Factor the delay grows by on each consecutive exit, at least 1.
+    backoff_multiplier: f64,
+
+    #[clap(long, default_value = "30s", parse(try_from_str = parse_duration))]
+    /// Maximum delay between restarts.
+    backoff_max: Duration,
+
+    #[clap(long, default_value = "10s", parse(try_from_str = parse_duration))]
+    /// Reset the delay once the command has run for this long.
+    backoff_reset: Duration,
+
+    #[clap(long, default_value = "TERM")]
+    /// Signal sent to the command to stop it.
+    stop_signal: Signal,
+
+    #[clap(long)]
+    /// Don't run the command in its own process group, only signal the
+    /// command itself rather than everything it started. Needed for
+    /// commands that read from the terminal.
+    no_process_group: bool,
+
+    #[clap(long, default_value = "10s", parse(try_from_str = parse_duration))]
+    /// Time to wait for the command to exit after the stop signal before
+    /// sending SIGKILL.
+    stop_timeout: Duration,
+
+    #[clap(long, multiple_occurrences(true), number_of_values(1))]
+    /// Listen on [HOST:]PORT and forward connections to the command's
+    /// [HOST:]PORT, holding them while it restarts and draining them before
+    /// it's stopped, may be repeated. Prefix with http: to answer requests
+    /// with a status page showing the last exit and recent stderr while the
+    /// command is down instead.
+    proxy: Vec<Proxy>,
+
+    #[clap(long, default_value = "30s", parse(try_from_str = parse_duration))]
+    /// Close proxied connections held for longer than this while the
+    /// command is down.
+    proxy_timeout: Duration,
+
+    #[clap(long, default_value = "5s", parse(try_from_str = parse_duration))]
+    /// Stop the command anyway once proxied connections have been draining
+    /// for this long.
+    drain_timeout: Duration,
+
+    #[clap(long)]
+    /// On a requested restart, start the new command before stopping the
+    /// old one, once the new one is ready. The old one is kept if the new
+    /// one isn't ready within --ready-timeout. Needs a --ready probe that
+    /// can't be answered by the old one: notify, output:REGEX or
+    /// cmd:COMMAND.
+    overlap: bool,
+
+    #[clap(long, arg_enum, default_value = "auto")]
+    /// Whether to prefix each line of output with a timestamp and the
+    /// process it came from, by default only when running several.
+    output: OutputMode,
+
+    #[clap(long, arg_enum, default_value = "auto")]
+    /// Whether to color prefixed output.
+    color: ColorMode,
+
+    #[clap(long)]
+    /// Also write output and rstrtr's messages to this file, piping the
+    /// command's output through rstrtr.
+    log_file: Option<PathBuf>,
+
+    #[clap(long, requires = "log-file")]
+    /// Write the command's stderr to this file rather than the log file.
+    stderr_log_file: Option<PathBuf>,
+
+    #[clap(long, default_value = "10M", parse(try_from_str = parse_size))]
+    /// Rotate log files once they reach this size.
+    log_max_size: u64,
+
+    #[clap(long, default_value = "5")]
+    /// Number of rotated log files to keep.
+    log_max_files: usize,
+
+    #[clap(long)]
+    /// How to tell the command is ready once started: tcp:[HOST:]PORT,
+    /// http://HOST:PORT/PATH expecting 2xx, output:REGEX matching a line of
+    /// stdout, cmd:COMMAND exiting 0, or notify for READY=1 sent to
+    /// systemd's NOTIFY_SOCKET.
+    ready: Option<Probe>,
+
+    #[clap(long, default_value = "30s", parse(try_from_str = parse_duration))]
+    /// Give up waiting for the command to be ready after this long.
+    ready_timeout: Duration,
+
+    #[clap(long, default_value = "250ms", parse(try_from_str = parse_duration))]
+    /// Time between readiness checks.
+    probe_interval: Duration,
+
+    #[clap(long)]
+    /// How to tell the command is still working once ready, restarting it
+    /// when it isn't: tcp:[HOST:]PORT, http://HOST:PORT/PATH or
+    /// cmd:COMMAND.
+    liveness: Option<Probe>,
+
+    #[clap(long, default_value = "10s", parse(try_from_str = parse_duration))]
+    /// Time between liveness checks.
+    liveness_interval: Duration,
+
+    #[clap(long, default_value = "1s", parse(try_from_str = parse_duration))]
+    /// Fail a liveness check that takes longer than this.
+    liveness_timeout: Duration,
+
+    #[clap(long, default_value = "3")]
+    /// Restart the command after this many liveness checks fail in a row.
+    liveness_failures: usize,
+
+    #[clap(long, parse(try_from_str = parse_duration))]
+    /// Set systemd's NOTIFY_SOCKET and WATCHDOG_USEC, restarting the
+    /// command once ready if it doesn't send WATCHDOG=1 this often.
+    watchdog: Option<Duration>,
+
+    #[clap(long, multiple_occurrences(true), number_of_values(1))]
+    /// Bind tcp:[HOST:]PORT or unix:PATH once and pass it to the command
+    /// with systemd's LISTEN_FDS, keeping it open across restarts, may be
+    /// repeated.
+    listen: Vec<Listen>,
+
+    #[clap(long, conflicts_with = "command")]
+    /// Run the named processes in this Procfile instead of one command.
+    procfile: Option<PathBuf>,
+
+    /// The command to run, unless set in rstrtr.toml. Separate with -- if
+    /// required.
+    command: Vec<String>,
 }
 
 fn main() -> anyhow::Result<()> {
-    let mut flags = Flags::parse();
+    let flags = Flags::parse();
+    let config = match &flags.config {
+        Some(path) => Some(Config::load(path)?),
+        None => Config::discover(&env::current_dir()?)?,
+    };
 
-    if flags.tmp_dir {
-        flags.rstrtr = env::temp_dir();
-        flags
-            .rstrtr
-            .push(format!("rstrtr.{}", calculate_hash(env::current_dir()?)));
-    }
+    let rstrtr = if flags.tmp_dir {
+        let mut rstrtr = env::temp_dir();
+        rstrtr.push(format!("rstrtr.{}", calculate_hash(env::current_dir()?)));
+        rstrtr
+    } else if let Some(rstrtr) = flags.rstrtr {
+        rstrtr
+    } else if let Some(config) = &config {
+        config
+            .control
+            .clone()
+            .unwrap_or_else(|| config.dir.join(".rstrtr"))
+    } else {
+        PathBuf::from("./.rstrtr")
+    };
 
+    let socket = control::socket_path(&rstrtr);
     match &flags.subcommand {
-        Subcommand::Run { command } => {
-            run(command, &flags)?;
+        Subcommand::Run(args) => {
+            let code = supervisor(args, config.as_ref())?
+                .control_path(&rstrtr)
+                .forward_signals(true)
+                .build()?
+                .run();
+            std::process::exit(code);
+        }
+        Subcommand::Restart { wait, process } => {
+            send(&socket, "restart", *wait, process, || {
+                std::fs::write(&rstrtr, "\n")
+            })?;
         }
-        Subcommand::Restart => {
-            std::fs::write(&flags.rstrtr, "\n")?;
+        Subcommand::Quit { process } => {
+            send(&socket, "quit", false, process, || {
+                std::fs::remove_file(&rstrtr)
+            })?;
         }
-        Subcommand::Quit => {
-            std::fs::remove_file(&flags.rstrtr)?;
+        Subcommand::Logs { follow, lines } => {
+            let request = match follow {
+                true => format!("logs {} follow", lines),
+                false => format!("logs {}", lines),
+            };
+            if control::stream(&socket, &request, |line| println!("{}", line))?.is_none() {
+                bail!("No rstrtr is listening on {}", socket.display());
+            }
+        }
+        Subcommand::Status { json } => {
+            let lines = match control::request(&socket, "status")? {
+                Some(lines) => lines,
+                None => bail!("No rstrtr is listening on {}", socket.display()),
+            };
+            let statuses = Status::from_lines(&lines)?;
+            if *json {
+                match statuses.as_slice() {
+                    [status] => println!("{}", status.to_json()),
+                    statuses => {
+                        let json: Vec<String> = statuses.iter().map(Status::to_json).collect();
+                        println!("[{}]", json.join(","));
+                    }
+                }
+            } else if let [status] = statuses.as_slice() {
+                print!("{}", status);
+            } else {
+                for (i, status) in statuses.iter().enumerate() {
+                    if i > 0 {
+                        println!();
+                    }
+                    println!("Name:         {}", status.name);
+                    print!("{}", status);
+                }
+            }
         }
     }
 
     Ok(())
 }
 
-fn calculate_hash(t: PathBuf) -> u64 {
-    let mut s = DefaultHasher::new();
-    t.hash(&mut s);
-    s.finish()
+/// Send `verb` for `process`, or all of them, to the rstrtr listening on
+/// `socket`. Without one, requests for all of them that don't wait fall
+/// back on `control_file`, which rstrtr watches.
+fn send(
+    socket: &Path,
+    verb: &str,
+    wait: bool,
+    process: &Option<String>,
+    control_file: impl FnOnce() -> io::Result<()>,
+) -> anyhow::Result<()> {
+    let mut request = verb.to_string();
+    if wait {
+        request.push_str(" --wait");
+    }
+    if let Some(name) = process {
+        request.push(' ');
+        request.push_str(name);
+    }
+    if control::request(socket, &request)?.is_none() {
+        match (wait, process) {
+            (false, None) => control_file()?,
+            _ => bail!("No rstrtr is listening on {}", socket.display()),
+        }
+    }
+    Ok(())
 }
 
-fn run(command: &[String], flags: &Flags) -> anyhow::Result<()> {
-    std::fs::write(&flags.rstrtr, "\n")?;
-
-    let (tx, rx) = channel();
-    let mut watcher = watcher(tx, Duration::from_millis(100))?;
-    watcher.watch(&flags.rstrtr, RecursiveMode::NonRecursive)?;
-
-    let mut keep_going = true;
-    while keep_going {
-        let mut proc = {
-            let res = Command::new(&command[0]).args(&command[1..]).spawn();
-            match res {
-                Err(e) => {
-                    println!("Error {:?} executing command", e);
-                    break;
-                }
-                Ok(proc) => proc,
+/// Configure a supervisor from the `run` flags, falling back to `config`
+/// for anything not given on the command line.
+fn supervisor(args: &RunArgs, config: Option<&Config>) -> anyhow::Result<SupervisorBuilder> {
+    let default = Config::default();
+    let config = config.unwrap_or(&default);
+    let command = if args.command.is_empty() && args.procfile.is_none() {
+        &config.defaults.command
+    } else {
+        &args.command
+    };
+    let builder = if command.is_empty() {
+        let procfile = args.procfile.as_ref().or(config.procfile.as_ref());
+        let processes = processes(procfile, config, args.restart.is_none())?;
+        if processes.is_empty() {
+            bail!(
+                "No command to run, pass one or a --procfile, or set `command` in {}",
+                config::FILE_NAME
+            );
+        }
+        Supervisor::with_processes(processes)
+    } else {
+        Supervisor::builder(command)
+    };
+
+    let mut builder = builder
+        .restart_policy(
+            args.restart
+                .or(config.defaults.restart)
+                .unwrap_or(RestartPolicy::Always),
+        )
+        .vcs_ignore(!args.no_vcs_ignore)
+        .backoff(
+            args.backoff_initial,
+            args.backoff_multiplier,
+            args.backoff_max,
+            args.backoff_reset,
+        )
+        .stop(args.stop_signal, args.stop_timeout)
+        .process_group(!args.no_process_group)
+        .overlap(args.overlap)
+        .proxy_timeouts(args.proxy_timeout, args.drain_timeout)
+        .output(args.output)
+        .color(args.color)
+        .log_rotation(args.log_max_size, args.log_max_files)
+        .ready_timeout(args.ready_timeout, args.probe_interval)
+        .liveness(
+            args.liveness_interval,
+            args.liveness_timeout,
+            args.liveness_failures,
+        );
+    if let Some(max) = args.max_restarts {
+        builder = builder.max_restarts(max, args.restart_window);
+    }
+    if let Some(watchdog) = args.watchdog {
+        builder = builder.watchdog(watchdog);
+    }
+    for path in or_config(&args.watch, &config.watch) {
+        builder = builder.watch(path);
+    }
+    for glob in or_config(&args.ignore, &config.ignore) {
+        builder = builder.ignore(glob);
+    }
+    for glob in or_config(&args.include, &config.include) {
+        builder = builder.include(glob);
+    }
+    if let Some(build) = &args.build {
+        builder = builder.build_command(build);
+    }
+    if let Some(path) = &args.log_file {
+        builder = builder.log_file(path);
+    }
+    if let Some(path) = &args.stderr_log_file {
+        builder = builder.stderr_log_file(path);
+    }
+    for (key, value) in &config.defaults.env {
+        builder = builder.env(key, value);
+    }
+    if let Some(cwd) = &config.defaults.cwd {
+        builder = builder.current_dir(cwd);
+    }
+    if let Some(probe) = args.ready.as_ref().or(config.defaults.ready.as_ref()) {
+        builder = builder.ready_probe(probe.clone());
+    }
+    for listen in or_config(&args.listen, &config.defaults.listen) {
+        builder = builder.listen(listen.clone());
+    }
+    for proxy in or_config(&args.proxy, &config.defaults.proxy) {
+        builder = builder.proxy(proxy.clone());
+    }
+    if let Some(probe) = args.liveness.as_ref().or(config.defaults.liveness.as_ref()) {
+        builder = builder.liveness_probe(probe.clone());
+    }
+    Ok(builder)
+}
+
+/// The processes in `procfile`, added to or refined by those in `config`.
+/// Their own restart policies are ignored unless `restart`.
+fn processes(
+    procfile: Option<&PathBuf>,
+    config: &Config,
+    restart: bool,
+) -> anyhow::Result<Vec<Process>> {
+    let mut processes = match procfile {
+        Some(path) => procfile::load(path)?
+            .into_iter()
+            .map(|(name, command)| ProcessConfig {
+                name,
+                command: vec!["sh".to_string(), "-c".to_string(), command],
+                ..ProcessConfig::default()
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    for process in &config.processes {
+        match processes.iter_mut().find(|p| p.name == process.name) {
+            Some(p) => p.merge(process),
+            None => processes.push(process.clone()),
+        }
+    }
+
+    processes
+        .into_iter()
+        .map(|p| {
+            if p.command.is_empty() {
+                bail!("No command to run for process {}", p.name);
             }
-        };
-
-        loop {
-            let mut restart = false;
-            if let Ok(msg) = rx.recv_timeout(Duration::from_millis(50)) {
-                match msg {
-                    DebouncedEvent::Write(_) => {
-                        restart = true;
-                    }
-                    DebouncedEvent::Remove(_) => {
-                        keep_going = false;
-                    }
-                    _ => {}
-                };
+            let mut process = Process::new(p.name, p.command);
+            for (key, value) in p.env {
+                process = process.env(key, value);
             }
-            if restart || !keep_going {
-                let _ = proc.kill();
+            if let Some(cwd) = p.cwd {
+                process = process.current_dir(cwd);
             }
-            if !keep_going {
-                break;
+            if let Some(probe) = p.ready {
+                process = process.ready_probe(probe);
             }
-            match proc.try_wait() {
-                Ok(Some(exit)) => {
-                    println!("Exit {}", exit);
-                    break;
-                }
-                Ok(None) => {}
-                Err(e) => {
-                    println!("Error {:?} try_wait on process", e);
-                }
+            if let Some(probe) = p.liveness {
+                process = process.liveness_probe(probe);
+            }
+            for listen in p.listen {
+                process = process.listen(listen);
+            }
+            for proxy in p.proxy {
+                process = process.proxy(proxy);
             }
+            match p.restart {
+                Some(policy) if restart => Ok(process.restart_policy(policy)),
+                _ => Ok(process),
+            }
+        })
+        .collect()
+}
+
+/// Values given on the command line replace those from the config file.
+fn or_config<'a, T>(cli: &'a [T], config: &'a [T]) -> &'a [T] {
+    if cli.is_empty() {
+        config
+    } else {
+        cli
+    }
+}
+
+fn calculate_hash(t: PathBuf) -> u64 {
+    let mut s = DefaultHasher::new();
+    t.hash(&mut s);
+    s.finish()
+}
+
+/// Parse a backoff multiplier, a number of at least 1.
+fn parse_multiplier(s: &str) -> anyhow::Result<f64> {
+    let multiplier: f64 = s.parse()?;
+    if !multiplier.is_finite() || multiplier < 1.0 {
+        bail!(
+            "invalid multiplier {:?}, expected a number of at least 1",
+            s
+        );
+    }
+    Ok(multiplier)
+}
+
+/// Parse a duration such as `500ms`, `10s`, `2m` or `1h`, bare numbers are
+/// seconds.
+fn parse_duration(s: &str) -> anyhow::Result<Duration> {
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.');
+    let (num, unit) = s.split_at(split.unwrap_or(s.len()));
+    let num: f64 = num
+        .parse()
+        .map_err(|_| anyhow!("invalid duration {:?}", s))?;
+    let secs = match unit {
+        "ms" => num / 1000.0,
+        "" | "s" => num,
+        "m" => num * 60.0,
+        "h" => num * 3600.0,
+        _ => return Err(anyhow!("invalid duration unit {:?} in {:?}", unit, s)),
+    };
+    Duration::try_from_secs_f64(secs).map_err(|_| anyhow!("duration {:?} is too long", s))
+}
+
+/// Parse a size such as `512K`, `10M` or `1G`, bare numbers are bytes.
+fn parse_size(s: &str) -> anyhow::Result<u64> {
+    let split = s.find(|c: char| !c.is_ascii_digit());
+    let (num, unit) = s.split_at(split.unwrap_or(s.len()));
+    let num: u64 = num.parse().map_err(|_| anyhow!("invalid size {:?}", s))?;
+    let scale = match unit {
+        "" | "B" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return Err(anyhow!("invalid size unit {:?} in {:?}", unit, s)),
+    };
+    num.checked_mul(scale)
+        .ok_or_else(|| anyhow!("size {:?} is too big", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        let cases = [
+            ("250ms", Duration::from_millis(250)),
+            ("1.5s", Duration::from_millis(1500)),
+            ("10", Duration::from_secs(10)),
+            ("2m", Duration::from_secs(120)),
+            ("1h", Duration::from_secs(3600)),
+            ("0s", Duration::ZERO),
+        ];
+        for (s, duration) in cases {
+            assert_eq!(parse_duration(s).unwrap(), duration, "{:?}", s);
         }
-        if keep_going {
-            println!("Restarting...");
+        for s in [
+            "",
+            "s",
+            "1.2.3s",
+            "-1s",
+            "1d",
+            "1 s",
+            "1e3s",
+            "9999999999999999999999h",
+        ] {
+            assert!(parse_duration(s).is_err(), "{:?}", s);
         }
     }
-    println!("Quitting...");
 
-    Ok(())
+    #[test]
+    fn sizes() {
+        let cases = [
+            ("512", 512),
+            ("512B", 512),
+            ("4K", 4096),
+            ("10M", 10 << 20),
+            ("1G", 1 << 30),
+        ];
+        for (s, size) in cases {
+            assert_eq!(parse_size(s).unwrap(), size, "{:?}", s);
+        }
+        for s in ["", "K", "1.5M", "1k", "1T", "-1", "99999999999999G"] {
+            assert!(parse_size(s).is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn multipliers() {
+        assert_eq!(parse_multiplier("1").unwrap(), 1.0);
+        assert_eq!(parse_multiplier("2.5").unwrap(), 2.5);
+        for s in ["0.5", "-1", "NaN", "inf", "x"] {
+            assert!(parse_multiplier(s).is_err(), "{:?}", s);
+        }
+    }
 }
@@ -0,0 +1,260 @@
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    io::{BufRead, BufReader, Read, Write},
+    mem,
+    os::unix::net::UnixStream,
+    ptr,
+    sync::{
+        mpsc::{channel, Receiver},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use clap::ArgEnum;
+use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+
+use crate::{control::Reply, log_file::LogFile};
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Prefix output when running several processes.
+    Auto,
+    /// Let processes write straight to the terminal.
+    Raw,
+    /// Prefix each line with a timestamp and the process it came from.
+    Prefixed,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Color prefixes when writing to a terminal and NO_COLOR isn't set.
+    Auto,
+    Always,
+    Never,
+}
+
+const PALETTE: [Color; 6] = [
+    Color::Cyan,
+    Color::Yellow,
+    Color::Green,
+    Color::Magenta,
+    Color::Blue,
+    Color::Red,
+];
+
+/// Where output is logged to, `stderr` is the process's stdout too unless
+/// given.
+pub(crate) struct Logs {
+    pub stdout: LogFile,
+    pub stderr: Option<LogFile>,
+}
+
+/// Writes rstrtr's messages and, when prefixed or logged, the processes'
+/// output piped through it.
+pub(crate) struct Output {
+    prefixed: bool,
+    piped: bool,
+    width: usize,
+    streams: Mutex<Streams>,
+}
+
+/// Recent lines kept for the `logs` request.
+const HISTORY: usize = 1000;
+
+struct Streams {
+    stdout: StandardStream,
+    stderr: StandardStream,
+    logs: Option<Logs>,
+    history: VecDeque<String>,
+    /// Control socket connections following new lines.
+    followers: Vec<UnixStream>,
+}
+
+impl Output {
+    /// Output for the processes `names`, each prefixed with a stable color.
+    /// Their output is piped through rstrtr if prefixed, logged or `piped`.
+    pub fn new(
+        names: &[String],
+        prefixed: bool,
+        piped: bool,
+        color: bool,
+        logs: Option<Logs>,
+    ) -> Self {
+        let choice = if color {
+            ColorChoice::Always
+        } else {
+            ColorChoice::Never
+        };
+        Self {
+            prefixed,
+            piped: piped || prefixed || logs.is_some(),
+            width: names
+                .iter()
+                .map(String::len)
+                .fold("rstrtr".len(), usize::max),
+            streams: Mutex::new(Streams {
+                stdout: StandardStream::stdout(choice),
+                stderr: StandardStream::stderr(choice),
+                logs,
+                history: VecDeque::new(),
+                followers: Vec::new(),
+            }),
+        }
+    }
+
+    /// Whether the processes' output needs to be piped through rstrtr.
+    pub fn piped(&self) -> bool {
+        self.piped
+    }
+
+    /// Write one of rstrtr's own messages.
+    pub fn message(&self, msg: impl Display) {
+        self.write("rstrtr", None, false, &msg.to_string());
+    }
+
+    /// Answer `reply` with the last `lines` lines written, then keep sending
+    /// new ones if `follow` until [`Output::close`].
+    pub fn tail(&self, lines: usize, follow: bool, reply: Reply) {
+        let mut streams = self.streams.lock().unwrap();
+        let skip = streams.history.len().saturating_sub(lines);
+        let tail: Vec<String> = streams.history.iter().skip(skip).cloned().collect();
+        match reply {
+            Reply::Socket(mut stream) if follow => {
+                let _ = stream.set_write_timeout(Some(Duration::from_millis(100)));
+                if tail.iter().all(|line| writeln!(stream, "{}", line).is_ok()) {
+                    streams.followers.push(stream);
+                }
+            }
+            reply => reply.send_lines(&tail),
+        }
+    }
+
+    /// Stop following, answering the followers.
+    pub fn close(&self) {
+        let followers = mem::take(&mut self.streams.lock().unwrap().followers);
+        for stream in followers {
+            Reply::Socket(stream).send(Ok(()));
+        }
+    }
+
+    /// Write the lines read from `reader`, the stdout or `stderr` of
+    /// process `i` named `name`, from a background thread until it closes,
+    /// passing each to `on_line` too. The returned receiver disconnects once
+    /// it has.
+    pub fn forward<R, F>(
+        self: &Arc<Self>,
+        i: usize,
+        name: &str,
+        stderr: bool,
+        reader: R,
+        mut on_line: F,
+    ) -> Receiver<()>
+    where
+        R: Read + Send + 'static,
+        F: FnMut(&str) + Send + 'static,
+    {
+        let output = self.clone();
+        let name = name.to_string();
+        let color = PALETTE[i % PALETTE.len()];
+        let (done, closed) = channel();
+        thread::spawn(move || {
+            let _done = done;
+            let mut reader = BufReader::new(reader);
+            let mut buf = Vec::new();
+            loop {
+                buf.clear();
+                match reader.read_until(b'\n', &mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {
+                        let line = String::from_utf8_lossy(&buf);
+                        let line = line.strip_suffix('\n').unwrap_or(&line);
+                        output.write(&name, Some(color), stderr, line);
+                        on_line(line);
+                    }
+                }
+            }
+        });
+        closed
+    }
+
+    fn write(&self, name: &str, color: Option<Color>, stderr: bool, line: &str) {
+        let mut streams = self.streams.lock().unwrap();
+        let Streams {
+            stdout,
+            stderr: err,
+            logs,
+            history,
+            followers,
+        } = &mut *streams;
+        let tm = local_time();
+
+        let recent = format!(
+            "{:02}:{:02}:{:02} {:width$} | {}",
+            tm.tm_hour,
+            tm.tm_min,
+            tm.tm_sec,
+            name,
+            line,
+            width = self.width
+        );
+        followers.retain_mut(|stream| writeln!(stream, "{}", recent).is_ok());
+        if history.len() == HISTORY {
+            history.pop_front();
+        }
+        history.push_back(recent);
+
+        if let Some(logs) = logs {
+            let log = match &mut logs.stderr {
+                Some(log) if stderr => log,
+                _ => &mut logs.stdout,
+            };
+            let _ = log.write_line(&format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02} {:width$} | {}",
+                tm.tm_year + 1900,
+                tm.tm_mon + 1,
+                tm.tm_mday,
+                tm.tm_hour,
+                tm.tm_min,
+                tm.tm_sec,
+                name,
+                line,
+                width = self.width
+            ));
+        }
+
+        let stream = if stderr { err } else { stdout };
+        if !self.prefixed {
+            let _ = writeln!(stream, "{}", line);
+            return;
+        }
+        let mut spec = ColorSpec::new();
+        match color {
+            Some(color) => spec.set_fg(Some(color)),
+            None => spec.set_bold(true),
+        };
+        let _ = stream.set_color(&spec);
+        let _ = write!(
+            stream,
+            "{:02}:{:02}:{:02} {:width$} |",
+            tm.tm_hour,
+            tm.tm_min,
+            tm.tm_sec,
+            name,
+            width = self.width
+        );
+        let _ = stream.reset();
+        let _ = writeln!(stream, " {}", line);
+    }
+}
+
+fn local_time() -> libc::tm {
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    unsafe {
+        let now = libc::time(ptr::null_mut());
+        libc::localtime_r(&now, &mut tm);
+    }
+    tm
+}
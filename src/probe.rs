@@ -0,0 +1,231 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    os::unix::process::CommandExt,
+    path::PathBuf,
+    process::{Command, Stdio},
+    str::FromStr,
+    sync::mpsc::{channel, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail};
+
+use crate::{regex::Regex, signal};
+
+/// A check that a process is up, such as ready to serve after starting.
+#[derive(Clone, Debug)]
+pub enum Probe {
+    /// Connect to `host:port`.
+    Tcp(String),
+    /// GET `path` from `host:port`, expecting a 2xx response.
+    Http { addr: String, path: String },
+    /// Match a line of the process's stdout, which is then piped through
+    /// rstrtr.
+    Output(Regex),
+    /// Run a shell command in the process's environment, expecting it to
+    /// exit 0.
+    Command(String),
+    /// Wait for `READY=1` on the process's `NOTIFY_SOCKET`, as systemd's
+    /// `sd_notify` sends it.
+    Notify,
+}
+
+impl FromStr for Probe {
+    type Err = anyhow::Error;
+
+    /// Parse `tcp:[HOST:]PORT`, `http://HOST[:PORT][/PATH]`, `output:REGEX`,
+    /// `cmd:COMMAND` or `notify`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "notify" {
+            return Ok(Probe::Notify);
+        }
+        if let Some(addr) = s.strip_prefix("tcp:") {
+            let addr = match addr.contains(':') {
+                true => addr.to_string(),
+                false => format!("localhost:{}", addr),
+            };
+            return Ok(Probe::Tcp(addr));
+        }
+        if let Some(url) = s.strip_prefix("http://") {
+            let (host, path) = url.split_at(url.find('/').unwrap_or(url.len()));
+            if host.is_empty() {
+                bail!("no host in probe {:?}", s);
+            }
+            let addr = match host.contains(':') {
+                true => host.to_string(),
+                false => format!("{}:80", host),
+            };
+            let path = match path {
+                "" => "/".to_string(),
+                path => path.to_string(),
+            };
+            return Ok(Probe::Http { addr, path });
+        }
+        if let Some(regex) = s.strip_prefix("output:") {
+            return Ok(Probe::Output(regex.parse()?));
+        }
+        if let Some(command) = s.strip_prefix("cmd:") {
+            return Ok(Probe::Command(command.to_string()));
+        }
+        Err(anyhow!(
+            "invalid probe {:?}, expected tcp:PORT, http://HOST:PORT/PATH, output:REGEX, cmd:COMMAND or notify",
+            s
+        ))
+    }
+}
+
+/// Where a process runs, for command probes.
+pub(crate) struct Context {
+    pub env: Vec<(String, String)>,
+    pub dir: Option<PathBuf>,
+    /// How long each check may take.
+    pub timeout: Duration,
+}
+
+impl Probe {
+    /// Check once, failing with why. Output and notify probes are matched
+    /// as lines and messages arrive instead.
+    pub(crate) fn check(&self, cx: &Context) -> Result<(), String> {
+        match self {
+            Probe::Tcp(addr) => connect(addr, cx.timeout).map(drop),
+            Probe::Http { addr, path } => {
+                let mut stream = connect(addr, cx.timeout)?;
+                let _ = stream.set_read_timeout(Some(cx.timeout));
+                let _ = stream.set_write_timeout(Some(cx.timeout));
+                let host = addr.rsplit_once(':').map_or(&addr[..], |(host, _)| host);
+                write!(
+                    stream,
+                    "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+                    path, host
+                )
+                .map_err(|e| format!("Error {:?} sending request to {}", e.kind(), addr))?;
+                let mut response = [0; 12];
+                stream
+                    .read_exact(&mut response)
+                    .map_err(|e| format!("Error {:?} reading response from {}", e.kind(), addr))?;
+                // Read the rest before closing, some servers log an error
+                // when reset while still writing.
+                let deadline = Instant::now() + cx.timeout;
+                let mut rest = [0; 4096];
+                while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+                    let _ = stream.set_read_timeout(Some(left));
+                    if !matches!(stream.read(&mut rest), Ok(n) if n > 0) {
+                        break;
+                    }
+                }
+                let response = String::from_utf8_lossy(&response);
+                match response.split_once(' ') {
+                    Some((version, status)) if version.starts_with("HTTP/") => {
+                        match status.starts_with('2') {
+                            true => Ok(()),
+                            false => Err(format!("GET {} returned {}", path, status.trim())),
+                        }
+                    }
+                    _ => Err(format!("Invalid HTTP response from {}", addr)),
+                }
+            }
+            Probe::Output(_) | Probe::Notify => unreachable!(),
+            Probe::Command(command) => {
+                let mut cmd = Command::new("sh");
+                cmd.arg("-c")
+                    .arg(command)
+                    .envs(cx.env.iter().map(|(key, value)| (key, value)))
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null());
+                if let Some(dir) = &cx.dir {
+                    cmd.current_dir(dir);
+                }
+                unsafe { cmd.pre_exec(signal::unblock) };
+                let mut child = cmd
+                    .spawn()
+                    .map_err(|e| format!("Error {:?} executing {}", e.kind(), command))?;
+                let deadline = Instant::now() + cx.timeout;
+                loop {
+                    match child.try_wait() {
+                        Ok(Some(exit)) if exit.success() => return Ok(()),
+                        Ok(Some(exit)) => return Err(format!("{} failed {}", command, exit)),
+                        Ok(None) if Instant::now() < deadline => {
+                            thread::sleep(Duration::from_millis(10))
+                        }
+                        Ok(None) => {
+                            let _ = child.kill();
+                            let _ = child.wait();
+                            return Err(format!("{} timed out after {:?}", command, cx.timeout));
+                        }
+                        Err(e) => {
+                            return Err(format!("Error {:?} waiting on {}", e.kind(), command))
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn connect(addr: &str, timeout: Duration) -> Result<TcpStream, String> {
+    let mut err = format!("Unable to resolve {}", addr);
+    let addrs = addr
+        .to_socket_addrs()
+        .map_err(|e| format!("Error {:?} resolving {}", e.kind(), addr))?;
+    for sock_addr in addrs {
+        match TcpStream::connect_timeout(&sock_addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => err = format!("Error {:?} connecting to {}", e.kind(), addr),
+        }
+    }
+    Err(err)
+}
+
+/// Check `probe` every `interval` from a background thread until it passes,
+/// then call `on_pass`. Dropping the returned sender stops checking.
+pub(crate) fn poll<F>(probe: Probe, cx: Context, interval: Duration, on_pass: F) -> Sender<()>
+where
+    F: FnOnce() + Send + 'static,
+{
+    let (stop, stopped) = channel::<()>();
+    thread::spawn(move || loop {
+        if probe.check(&cx).is_ok() {
+            on_pass();
+            return;
+        }
+        if stopped.recv_timeout(interval) != Err(RecvTimeoutError::Timeout) {
+            return;
+        }
+    });
+    stop
+}
+
+/// Check `probe` every `interval` from a background thread, calling
+/// `on_fail` with the last reason once it fails `failures` times in a row.
+/// Dropping the returned sender stops checking.
+pub(crate) fn watch<F>(
+    probe: Probe,
+    cx: Context,
+    interval: Duration,
+    failures: usize,
+    on_fail: F,
+) -> Sender<()>
+where
+    F: FnOnce(String) + Send + 'static,
+{
+    let (stop, stopped) = channel::<()>();
+    thread::spawn(move || {
+        let mut failed = 0;
+        while stopped.recv_timeout(interval) == Err(RecvTimeoutError::Timeout) {
+            match probe.check(&cx) {
+                Ok(()) => failed = 0,
+                Err(e) => {
+                    failed += 1;
+                    if failed >= failures {
+                        on_fail(e);
+                        return;
+                    }
+                }
+            }
+        }
+    });
+    stop
+}
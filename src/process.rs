@@ -0,0 +1,102 @@
+use std::path::{Path, PathBuf};
+
+use crate::{listen::Listen, probe::Probe, proxy::Proxy, supervisor::RestartPolicy};
+
+/// A named command for a [`Supervisor`](crate::Supervisor) to run, settings
+/// not given here are taken from the
+/// [`SupervisorBuilder`](crate::SupervisorBuilder).
+#[derive(Clone, Debug)]
+pub struct Process {
+    pub(crate) name: String,
+    pub(crate) command: Vec<String>,
+    pub(crate) env: Vec<(String, String)>,
+    pub(crate) current_dir: Option<PathBuf>,
+    pub(crate) restart: Option<RestartPolicy>,
+    pub(crate) ready: Option<Probe>,
+    pub(crate) liveness: Option<Probe>,
+    pub(crate) listen: Vec<Listen>,
+    pub(crate) proxy: Vec<Proxy>,
+}
+
+impl Process {
+    /// `command` is a program followed by its arguments.
+    pub fn new<I, S>(name: impl Into<String>, command: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            name: name.into(),
+            command: command.into_iter().map(Into::into).collect(),
+            env: Vec::new(),
+            current_dir: None,
+            restart: None,
+            ready: None,
+            liveness: None,
+            listen: Vec::new(),
+            proxy: Vec::new(),
+        }
+    }
+
+    /// Run `command` with `sh -c`.
+    pub fn shell(name: impl Into<String>, command: impl Into<String>) -> Self {
+        Self::new(name, ["sh".to_string(), "-c".to_string(), command.into()])
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Set an environment variable, on top of those for every process.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Run the command in `dir`.
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    /// When to restart this process after it exits by itself.
+    pub fn restart_policy(mut self, restart: RestartPolicy) -> Self {
+        self.restart = Some(restart);
+        self
+    }
+
+    /// How to tell this process is ready once started.
+    pub fn ready_probe(mut self, probe: Probe) -> Self {
+        self.ready = Some(probe);
+        self
+    }
+
+    /// How to tell this process is still working once ready.
+    pub fn liveness_probe(mut self, probe: Probe) -> Self {
+        self.liveness = Some(probe);
+        self
+    }
+
+    /// Pass this process a socket listening on `listen`, instead of those
+    /// for every process.
+    pub fn listen(mut self, listen: Listen) -> Self {
+        self.listen.push(listen);
+        self
+    }
+
+    /// Forward connections to this process through `proxy`, instead of
+    /// those for every process.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy.push(proxy);
+        self
+    }
+}
+
+/// Name a process after its program.
+pub(crate) fn program_name(command: &[String]) -> String {
+    command
+        .first()
+        .and_then(|program| Path::new(program).file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
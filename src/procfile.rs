@@ -0,0 +1,29 @@
+use std::{fs, path::Path};
+
+use anyhow::anyhow;
+
+/// Read the `name: command` lines of a Procfile.
+pub fn load(path: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let src =
+        fs::read_to_string(path).map_err(|e| anyhow!("Error reading {}: {}", path.display(), e))?;
+    let mut processes = Vec::new();
+    for (n, line) in src.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, command) = line
+            .split_once(':')
+            .map(|(name, command)| (name.trim(), command.trim()))
+            .filter(|(name, command)| {
+                !name.is_empty()
+                    && !command.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            })
+            .ok_or_else(|| anyhow!("{}:{}: expected `name: command`", path.display(), n + 1))?;
+        processes.push((name.to_string(), command.to_string()));
+    }
+    Ok(processes)
+}
@@ -0,0 +1,336 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs},
+    str::FromStr,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+
+/// Recent stderr lines shown on the HTTP status page.
+const STDERR_LINES: usize = 50;
+
+/// Forward connections from a port rstrtr listens on to the port a process
+/// listens on, holding them while the process restarts. HTTP proxies
+/// instead answer with a status page while it's down.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proxy {
+    listen: String,
+    upstream: String,
+    http: bool,
+}
+
+impl FromStr for Proxy {
+    type Err = anyhow::Error;
+
+    /// Parse `[http:][HOST:]PORT=[HOST:]PORT`, listening on all interfaces
+    /// and forwarding to localhost by default.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (http, spec) = match s.strip_prefix("http:") {
+            Some(spec) => (true, spec),
+            None => (false, s),
+        };
+        let (listen, upstream) = spec
+            .split_once('=')
+            .filter(|(listen, upstream)| !listen.is_empty() && !upstream.is_empty())
+            .ok_or_else(|| anyhow!("invalid proxy {:?}, expected [http:]LISTEN=UPSTREAM", s))?;
+        let addr = |addr: &str, host: &str| match addr.contains(':') {
+            true => addr.to_string(),
+            false => format!("{}:{}", host, addr),
+        };
+        Ok(Self {
+            listen: addr(listen, "0.0.0.0"),
+            upstream: addr(upstream, "127.0.0.1"),
+            http,
+        })
+    }
+}
+
+impl Proxy {
+    pub(crate) fn is_http(&self) -> bool {
+        self.http
+    }
+
+    pub(crate) fn bind(&self) -> anyhow::Result<TcpListener> {
+        TcpListener::bind(&self.listen)
+            .map_err(|e| anyhow!("Error listening on {}: {}", self.listen, e))
+    }
+
+    /// Forward connections accepted by `listener` from a background thread,
+    /// through `gate`, giving up on those held for longer than `timeout`.
+    pub(crate) fn serve(&self, listener: TcpListener, gate: Arc<Gate>, timeout: Duration) {
+        let upstream = self.upstream.clone();
+        let http = self.http;
+        thread::spawn(move || {
+            for client in listener.incoming().flatten() {
+                let upstream = upstream.clone();
+                let gate = gate.clone();
+                thread::spawn(move || {
+                    if let Some(generation) = gate.enter(&upstream, client, http, timeout) {
+                        gate.leave(generation);
+                    }
+                });
+            }
+        });
+    }
+}
+
+/// Lets connections through to a process while it is up, and tracks those
+/// made so they can be drained before it's stopped.
+pub(crate) struct Gate {
+    name: String,
+    state: Mutex<GateState>,
+    changed: Condvar,
+}
+
+/// What the process is doing, for the status page.
+#[derive(Default)]
+enum Phase {
+    #[default]
+    NotStarted,
+    Starting,
+    Stopping,
+    Exited,
+}
+
+#[derive(Default)]
+struct GateState {
+    up: bool,
+    /// Bumped on draining, connections are counted by the generation they
+    /// were made in.
+    generation: u64,
+    active: HashMap<u64, usize>,
+    /// Called once the generations before `generation` have no
+    /// connections.
+    drained: Option<Box<dyn FnOnce() + Send>>,
+    /// Shown on the HTTP status page.
+    phase: Phase,
+    last_exit: Option<String>,
+    stderr: VecDeque<String>,
+}
+
+impl GateState {
+    fn check_drained(&mut self) {
+        let generation = self.generation;
+        if self.active.keys().all(|g| *g >= generation) {
+            if let Some(drained) = self.drained.take() {
+                drained();
+            }
+        }
+    }
+}
+
+impl Gate {
+    /// A gate for the process `name`, holding connections until up.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            state: Mutex::default(),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Record that the process was started, for the status page.
+    pub fn started(&self) {
+        self.state.lock().unwrap().phase = Phase::Starting;
+    }
+
+    /// Record that the process is being stopped, for the status page.
+    pub fn stopping(&self) {
+        self.state.lock().unwrap().phase = Phase::Stopping;
+    }
+
+    /// Record the process's exit status for the status page.
+    pub fn exited(&self, status: String) {
+        let mut state = self.state.lock().unwrap();
+        state.phase = Phase::Exited;
+        state.last_exit = Some(status);
+    }
+
+    /// Record a line the process wrote to stderr for the status page.
+    pub fn stderr(&self, line: &str) {
+        let mut state = self.state.lock().unwrap();
+        if state.stderr.len() == STDERR_LINES {
+            state.stderr.pop_front();
+        }
+        state.stderr.push_back(line.to_string());
+    }
+
+    /// Whether to let connections through, holding them otherwise.
+    pub fn set_up(&self, up: bool) {
+        self.state.lock().unwrap().up = up;
+        self.changed.notify_all();
+    }
+
+    /// Call `on_drained` once the connections made so far have closed,
+    /// returning false without calling it if there are none.
+    pub fn drain<F>(&self, on_drained: F) -> bool
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        if state.active.is_empty() {
+            state.drained = None;
+            return false;
+        }
+        state.drained = Some(Box::new(on_drained));
+        true
+    }
+
+    /// Forward `client` to `upstream` once up and listening, until either
+    /// closes, returning the generation it was counted in. Gives up if
+    /// that takes longer than `timeout`, or answers with the status page
+    /// at once if `http`.
+    fn enter(
+        &self,
+        upstream: &str,
+        client: TcpStream,
+        http: bool,
+        timeout: Duration,
+    ) -> Option<u64> {
+        let deadline = Instant::now() + timeout;
+        let (server, generation) = loop {
+            let mut state = self.state.lock().unwrap();
+            while !state.up {
+                if http {
+                    drop(state);
+                    self.unavailable(client);
+                    return None;
+                }
+                let left = deadline.checked_duration_since(Instant::now())?;
+                state = self.changed.wait_timeout(state, left).unwrap().0;
+            }
+            let generation = state.generation;
+            *state.active.entry(generation).or_default() += 1;
+            drop(state);
+
+            match connect(upstream) {
+                Ok(server) => break (server, generation),
+                Err(_) => {
+                    self.leave(generation);
+                    if http {
+                        self.unavailable(client);
+                        return None;
+                    }
+                    if Instant::now() >= deadline {
+                        return None;
+                    }
+                    thread::sleep(Duration::from_millis(50));
+                }
+            }
+        };
+        copy(client, server);
+        Some(generation)
+    }
+
+    fn leave(&self, generation: u64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(active) = state.active.get_mut(&generation) {
+            *active -= 1;
+            if *active == 0 {
+                state.active.remove(&generation);
+            }
+        }
+        state.check_drained();
+    }
+
+    /// Answer an HTTP request on `client` with a page showing why the
+    /// process is down, refreshing until it's back.
+    fn unavailable(&self, mut client: TcpStream) {
+        let _ = client.set_read_timeout(Some(Duration::from_secs(1)));
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 8192 {
+            match client.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => request.extend_from_slice(&buf[..n]),
+            }
+        }
+
+        let state = self.state.lock().unwrap();
+        let name = html_escape(&self.name);
+        let status = match (&state.phase, state.up) {
+            // Let through but refused, such as before it has bound its port.
+            (_, true) => "not accepting connections",
+            (Phase::NotStarted, false) => "not started yet",
+            (Phase::Starting, false) => "starting",
+            (Phase::Stopping, false) => "stopping",
+            (Phase::Exited, false) => "exited",
+        };
+        let last_exit = match &state.last_exit {
+            Some(last_exit) => html_escape(last_exit),
+            None => "none".to_string(),
+        };
+        let stderr: Vec<String> = state.stderr.iter().map(|line| html_escape(line)).collect();
+        drop(state);
+        let body = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <meta http-equiv=\"refresh\" content=\"1\">\n<title>{name} is down</title>\n</head>\n\
+             <body>\n<h1>{name} is down</h1>\n<p>Status: {status}</p>\n<p>Last exit: {last_exit}</p>\n\
+             <h2>Recent stderr</h2>\n<pre>{stderr}</pre>\n\
+             <p>This page refreshes until {name} is back.</p>\n</body>\n</html>\n",
+            name = name,
+            status = status,
+            last_exit = last_exit,
+            stderr = stderr.join("\n"),
+        );
+        let _ = write!(
+            client,
+            "HTTP/1.1 503 Service Unavailable\r\n\
+             Content-Type: text/html; charset=utf-8\r\n\
+             Content-Length: {}\r\n\
+             Retry-After: 1\r\n\
+             Cache-Control: no-store\r\n\
+             Connection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+    }
+}
+
+fn html_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn connect(addr: &str) -> io::Result<TcpStream> {
+    let mut err = io::Error::from(io::ErrorKind::NotFound);
+    for addr in addr.to_socket_addrs()? {
+        match TcpStream::connect(addr) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => err = e,
+        }
+    }
+    Err(err)
+}
+
+/// Copy between `client` and `server` both ways until the server is done
+/// writing, then stop reading from the client so an idle client can't hold
+/// up draining.
+fn copy(client: TcpStream, server: TcpStream) {
+    let (mut client_read, mut server_write) = match (client.try_clone(), server.try_clone()) {
+        (Ok(client_read), Ok(server_write)) => (client_read, server_write),
+        _ => return,
+    };
+    let upload = thread::spawn(move || {
+        let _ = io::copy(&mut client_read, &mut server_write);
+        let _ = server_write.shutdown(Shutdown::Write);
+    });
+    let (mut server_read, mut client_write) = (server, client);
+    let _ = io::copy(&mut server_read, &mut client_write);
+    let _ = client_write.shutdown(Shutdown::Both);
+    let _ = upload.join();
+}
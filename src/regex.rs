@@ -0,0 +1,445 @@
+use std::{iter::Peekable, str::Chars, str::FromStr};
+
+use anyhow::{anyhow, bail};
+
+#[derive(Clone, Debug)]
+enum Node {
+    Char(char),
+    Any,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+    Start,
+    End,
+    /// Alternatives, each a sequence of nodes.
+    Group(Vec<Vec<Node>>),
+    /// Repeat `node` between `min` and `max` times.
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+/// A small regex supporting `.`, `[...]` classes, `\d`, `\w` and `\s`, `^`
+/// and `$` anchors, `*`, `+` and `?`, and `(...|...)` groups.
+///
+/// Unanchored regexes match anywhere in the string. Other syntax, such as
+/// `{n,m}` or `\n`, is rejected rather than matched literally, escape
+/// punctuation with `\` to match it.
+///
+/// Matching steps every possible match along the string at once, so it takes
+/// time and memory in proportion to the regex times the string, never
+/// recursing per character.
+#[derive(Clone, Debug)]
+pub struct Regex {
+    prog: Vec<Inst>,
+}
+
+/// An instruction of a compiled regex.
+#[derive(Clone, Debug)]
+enum Inst {
+    /// Consume a character matching the node.
+    Node(Node),
+    Start,
+    End,
+    /// Continue at both.
+    Split(usize, usize),
+    Jmp(usize),
+    Match,
+}
+
+impl Regex {
+    pub fn is_match(&self, s: &str) -> bool {
+        let chars: Vec<char> = s.chars().collect();
+        let mut seen = vec![usize::MAX; self.prog.len()];
+        let mut threads = Vec::new();
+        for i in 0..=chars.len() {
+            // Unanchored, so a match can start anywhere.
+            if self.add(&mut threads, &mut seen, 0, i, chars.len()) {
+                return true;
+            }
+            let c = match chars.get(i) {
+                Some(c) => *c,
+                None => break,
+            };
+            let mut next = Vec::new();
+            for pc in threads.drain(..) {
+                if let Inst::Node(node) = &self.prog[pc] {
+                    if node.matches(c) && self.add(&mut next, &mut seen, pc + 1, i + 1, chars.len())
+                    {
+                        return true;
+                    }
+                }
+            }
+            threads = next;
+        }
+        false
+    }
+
+    /// Add the thread at `pc` to `threads` waiting on the character at `i`,
+    /// following jumps and anchors, returning whether it reaches a match.
+    fn add(
+        &self,
+        threads: &mut Vec<usize>,
+        seen: &mut [usize],
+        pc: usize,
+        i: usize,
+        len: usize,
+    ) -> bool {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if seen[pc] == i {
+                continue;
+            }
+            seen[pc] = i;
+            match &self.prog[pc] {
+                Inst::Node(_) => threads.push(pc),
+                Inst::Start if i == 0 => stack.push(pc + 1),
+                Inst::End if i == len => stack.push(pc + 1),
+                Inst::Start | Inst::End => {}
+                Inst::Split(a, b) => {
+                    stack.push(*b);
+                    stack.push(*a);
+                }
+                Inst::Jmp(to) => stack.push(*to),
+                Inst::Match => return true,
+            }
+        }
+        false
+    }
+}
+
+impl Node {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Node::Char(want) => c == *want,
+            Node::Any => true,
+            Node::Class { negated, ranges } => {
+                ranges.iter().any(|(lo, hi)| *lo <= c && c <= *hi) != *negated
+            }
+            Node::Start | Node::End | Node::Group(_) | Node::Repeat { .. } => false,
+        }
+    }
+
+    fn compile(&self, prog: &mut Vec<Inst>) {
+        match self {
+            Node::Start => prog.push(Inst::Start),
+            Node::End => prog.push(Inst::End),
+            Node::Group(alternatives) => {
+                // Split off each alternative but the last, each jumping past
+                // the rest once matched.
+                let mut jumps = Vec::new();
+                for (n, nodes) in alternatives.iter().enumerate() {
+                    let split = prog.len();
+                    let last = n + 1 == alternatives.len();
+                    if !last {
+                        prog.push(Inst::Split(split + 1, 0));
+                    }
+                    for node in nodes {
+                        node.compile(prog);
+                    }
+                    if !last {
+                        jumps.push(prog.len());
+                        prog.push(Inst::Jmp(0));
+                        prog[split] = Inst::Split(split + 1, prog.len());
+                    }
+                }
+                for jump in jumps {
+                    prog[jump] = Inst::Jmp(prog.len());
+                }
+            }
+            Node::Repeat { node, min, max } => {
+                for _ in 0..*min {
+                    node.compile(prog);
+                }
+                match max {
+                    None => {
+                        let split = prog.len();
+                        prog.push(Inst::Split(split + 1, 0));
+                        node.compile(prog);
+                        prog.push(Inst::Jmp(split));
+                        prog[split] = Inst::Split(split + 1, prog.len());
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(prog.len());
+                            prog.push(Inst::Split(0, 0));
+                            node.compile(prog);
+                        }
+                        for split in splits {
+                            prog[split] = Inst::Split(split + 1, prog.len());
+                        }
+                    }
+                }
+            }
+            node => prog.push(Inst::Node(node.clone())),
+        }
+    }
+}
+
+impl FromStr for Regex {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars().peekable();
+        let mut alternatives = parse_alternatives(&mut chars, s)?;
+        if chars.next().is_some() {
+            bail!("unmatched ) in regex {:?}", s);
+        }
+        let nodes = match alternatives.len() {
+            1 => alternatives.pop().unwrap(),
+            _ => vec![Node::Group(alternatives)],
+        };
+        let mut prog = Vec::new();
+        for node in &nodes {
+            node.compile(&mut prog);
+        }
+        prog.push(Inst::Match);
+        Ok(Self { prog })
+    }
+}
+
+/// Parse `|` separated sequences up to an unmatched `)` or the end.
+fn parse_alternatives(chars: &mut Peekable<Chars>, s: &str) -> anyhow::Result<Vec<Vec<Node>>> {
+    let mut alternatives = vec![Vec::new()];
+    while let Some(c) = chars.peek().copied() {
+        let node = match c {
+            ')' => break,
+            '|' => {
+                chars.next();
+                alternatives.push(Vec::new());
+                continue;
+            }
+            '*' | '+' | '?' => bail!("nothing to repeat in regex {:?}", s),
+            '(' => {
+                chars.next();
+                let group = parse_alternatives(chars, s)?;
+                if chars.next() != Some(')') {
+                    bail!("unclosed ( in regex {:?}", s);
+                }
+                Node::Group(group)
+            }
+            _ => parse_atom(chars, s)?,
+        };
+        let (min, max) = match chars.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            _ => {
+                alternatives.last_mut().unwrap().push(node);
+                continue;
+            }
+        };
+        chars.next();
+        let node = Box::new(node);
+        alternatives
+            .last_mut()
+            .unwrap()
+            .push(Node::Repeat { node, min, max });
+    }
+    Ok(alternatives)
+}
+
+fn parse_atom(chars: &mut Peekable<Chars>, s: &str) -> anyhow::Result<Node> {
+    Ok(match chars.next().unwrap() {
+        '.' => Node::Any,
+        '^' => Node::Start,
+        '$' => Node::End,
+        '\\' => {
+            let c = chars
+                .next()
+                .ok_or_else(|| anyhow!("trailing \\ in regex {:?}", s))?;
+            match shorthand(c.to_ascii_lowercase()) {
+                Some(ranges) => Node::Class {
+                    negated: c.is_ascii_uppercase(),
+                    ranges,
+                },
+                None if c.is_ascii_alphanumeric() => {
+                    bail!("unsupported escape \\{} in regex {:?}", c, s)
+                }
+                None => Node::Char(c),
+            }
+        }
+        '{' => bail!("unsupported repetition {{...}} in regex {:?}", s),
+        '[' => {
+            let negated = chars.peek() == Some(&'^');
+            if negated {
+                chars.next();
+            }
+            let mut ranges = Vec::new();
+            loop {
+                let lo = match chars.next() {
+                    Some(']') if !ranges.is_empty() => break,
+                    Some('\\') => {
+                        let c = chars
+                            .next()
+                            .ok_or_else(|| anyhow!("unclosed [ in regex {:?}", s))?;
+                        if let Some(class) = shorthand(c) {
+                            ranges.extend(class);
+                            continue;
+                        }
+                        if c.is_ascii_alphanumeric() {
+                            bail!("unsupported escape \\{} in regex {:?}", c, s);
+                        }
+                        c
+                    }
+                    Some('[') if chars.peek() == Some(&':') => {
+                        bail!("unsupported class [:...:] in regex {:?}", s)
+                    }
+                    Some(c) => c,
+                    None => bail!("unclosed [ in regex {:?}", s),
+                };
+                let mut hi = lo;
+                if chars.peek() == Some(&'-') {
+                    chars.next();
+                    match chars.next() {
+                        Some(']') => {
+                            ranges.push((lo, lo));
+                            ranges.push(('-', '-'));
+                            break;
+                        }
+                        Some(c) => hi = c,
+                        None => bail!("unclosed [ in regex {:?}", s),
+                    }
+                }
+                ranges.push((lo, hi));
+            }
+            Node::Class { negated, ranges }
+        }
+        c => Node::Char(c),
+    })
+}
+
+/// The ranges of the `\d`, `\w` and `\s` classes.
+fn shorthand(c: char) -> Option<Vec<(char, char)>> {
+    match c {
+        'd' => Some(vec![('0', '9')]),
+        'w' => Some(vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')]),
+        's' => Some(vec![(' ', ' '), ('\t', '\r')]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(regex: &str, s: &str) -> bool {
+        regex.parse::<Regex>().unwrap().is_match(s)
+    }
+
+    #[test]
+    fn literals() {
+        assert!(is_match("ready", "server is ready now"));
+        assert!(!is_match("ready", "not read"));
+        assert!(is_match("", "anything"));
+        assert!(is_match("a\\.b", "a.b"));
+        assert!(!is_match("a\\.b", "axb"));
+        assert!(is_match("\\(\\*\\)", "(*)"));
+        assert!(is_match("}", "}"));
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(is_match("^ready", "ready now"));
+        assert!(!is_match("^ready", "not ready"));
+        assert!(is_match("ready$", "not ready"));
+        assert!(!is_match("ready$", "ready now"));
+        assert!(is_match("^$", ""));
+        assert!(!is_match("^$", "x"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(is_match("a.c", "abc"));
+        assert!(!is_match("a.c", "ac"));
+        assert!(is_match("[abc]x", "bx"));
+        assert!(!is_match("[abc]x", "dx"));
+        assert!(is_match("^[^abc]$", "d"));
+        assert!(!is_match("^[^abc]$", "a"));
+        assert!(is_match("^[a-z0-9_]+$", "snake_case_2"));
+        assert!(is_match("[]]", "]"));
+        assert!(is_match("[a-]", "-"));
+        assert!(is_match("[\\]\\\\]", "\\"));
+        assert!(is_match("^[\\d.]+$", "1.25"));
+    }
+
+    #[test]
+    fn shorthands() {
+        assert!(is_match("^\\d+$", "8080"));
+        assert!(!is_match("^\\d+$", "80a"));
+        assert!(is_match("^\\w+$", "Ab_9"));
+        assert!(!is_match("^\\w+$", "a-b"));
+        assert!(is_match("a\\sb", "a\tb"));
+        assert!(is_match("^\\D+$", "abc"));
+        assert!(!is_match("\\W", "abc"));
+        assert!(is_match("a\\Sb", "axb"));
+        assert!(!is_match("a\\Sb", "a b"));
+    }
+
+    #[test]
+    fn repetition() {
+        assert!(is_match("^ab*c$", "ac"));
+        assert!(is_match("^ab*c$", "abbbc"));
+        assert!(!is_match("^ab+c$", "ac"));
+        assert!(is_match("^ab+c$", "abc"));
+        assert!(is_match("^ab?c$", "ac"));
+        assert!(!is_match("^ab?c$", "abbc"));
+        // Greedy repeats backtrack to let the rest match.
+        assert!(is_match("^a.*b$", "axxbxxb"));
+        assert!(is_match("^(a*)*$", "aaa"));
+        assert!(!is_match("^(a*)*$", "aab"));
+    }
+
+    #[test]
+    fn groups() {
+        assert!(is_match("^(ready|listening)$", "listening"));
+        assert!(!is_match("^(ready|listening)$", "ready!"));
+        assert!(is_match("^ready|listening$", "ready!"));
+        assert!(is_match("^(ab)+$", "ababab"));
+        assert!(!is_match("^(ab)+$", "aba"));
+        assert!(is_match("^a(b|c(d|e))f$", "acef"));
+        assert!(is_match("^(|x)y$", "y"));
+        assert!(is_match(
+            "Listening on (http://)?[\\w.]+:\\d+",
+            "Listening on http://127.0.0.1:8080"
+        ));
+    }
+
+    #[test]
+    fn long_lines() {
+        let line = format!("a{}", "x".repeat(100_000));
+        assert!(!is_match("a.*b", &line));
+        assert!(is_match("a.*b", &format!("{}b", line)));
+        assert!(is_match("^(x|a)+$", &line));
+        assert!(!is_match("^(x*)*y$", &line));
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            "*a",
+            "a|+",
+            "(a",
+            "a)",
+            "a\\",
+            "[a",
+            "[a-",
+            "[]",
+            "a{2}",
+            "a{2,3}",
+            "\\n",
+            "\\b",
+            "\\1",
+            "[\\n]",
+            "[\\D]",
+            "[[:alpha:]]",
+        ];
+        for regex in cases {
+            assert!(regex.parse::<Regex>().is_err(), "{:?}", regex);
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::{
+    env, fs, io,
+    net::Shutdown,
+    os::unix::net::UnixDatagram,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+/// A message sent with systemd's `sd_notify`.
+pub(crate) enum Notification {
+    Ready,
+    Watchdog,
+    Status(String),
+}
+
+/// A `NOTIFY_SOCKET` for one child, removed when dropped.
+pub(crate) struct NotifySocket {
+    path: PathBuf,
+    socket: UnixDatagram,
+    /// Moved to the thread reading messages.
+    reader: Option<UnixDatagram>,
+}
+
+impl NotifySocket {
+    /// Bind a new socket in the temporary directory.
+    pub fn bind() -> io::Result<Self> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = env::temp_dir().join(format!(
+            "rstrtr.{}.{}.notify",
+            process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_file(&path);
+        let socket = UnixDatagram::bind(&path)?;
+        let reader = Some(socket.try_clone()?);
+        Ok(Self {
+            path,
+            socket,
+            reader,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Call `on_message` with each notification received, from a background
+    /// thread that stops once the socket is dropped.
+    pub fn listen<F>(&mut self, mut on_message: F)
+    where
+        F: FnMut(Notification) + Send + 'static,
+    {
+        let socket = match self.reader.take() {
+            Some(socket) => socket,
+            None => return,
+        };
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            // Shutting down on drop reads nothing, sd_notify never sends
+            // empty messages.
+            while let Ok(n @ 1..) = socket.recv(&mut buf) {
+                for line in String::from_utf8_lossy(&buf[..n]).lines() {
+                    match line.split_once('=') {
+                        Some(("READY", "1")) => on_message(Notification::Ready),
+                        Some(("WATCHDOG", "1")) => on_message(Notification::Watchdog),
+                        Some(("STATUS", status)) => {
+                            on_message(Notification::Status(status.to_string()))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl Drop for NotifySocket {
+    fn drop(&mut self) {
+        let _ = self.socket.shutdown(Shutdown::Both);
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,114 @@
+use std::{fmt, io, mem, ptr, str::FromStr, thread};
+
+use anyhow::anyhow;
+
+const NAMES: &[(&str, libc::c_int)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("USR2", libc::SIGUSR2),
+    ("TERM", libc::SIGTERM),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("WINCH", libc::SIGWINCH),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// A unix signal, parsed from a name (`TERM`, `SIGTERM`) or number (`15`).
+pub struct Signal(libc::c_int);
+
+/// Signals rstrtr forwards to the command before quitting.
+const FORWARDED: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+impl Signal {
+    pub const HUP: Signal = Signal(libc::SIGHUP);
+    pub const INT: Signal = Signal(libc::SIGINT);
+    pub const KILL: Signal = Signal(libc::SIGKILL);
+    pub const TERM: Signal = Signal(libc::SIGTERM);
+
+    /// Exit code of a process killed by this signal.
+    pub fn exit_code(self) -> i32 {
+        128 + self.0
+    }
+
+    /// Deliver this signal to the process `pid`, or to every process in
+    /// its process group if `group` is set.
+    pub fn send(self, pid: u32, group: bool) -> io::Result<()> {
+        let pid = pid as libc::pid_t;
+        let pid = if group { -pid } else { pid };
+        if unsafe { libc::kill(pid, self.0) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Signal {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(num) = s.parse::<libc::c_int>() {
+            return Ok(Signal(num));
+        }
+        let upper = s.to_ascii_uppercase();
+        let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+        NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, num)| Signal(*num))
+            .ok_or_else(|| anyhow!("unknown signal {:?}", s))
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match NAMES.iter().find(|(_, num)| *num == self.0) {
+            Some((name, _)) => write!(f, "SIG{}", name),
+            None => write!(f, "signal {}", self.0),
+        }
+    }
+}
+
+/// Block SIGINT, SIGTERM and SIGHUP in this thread and the threads it goes
+/// on to spawn, calling `handler` from a dedicated thread when one arrives.
+pub(crate) fn handle<F>(handler: F) -> io::Result<()>
+where
+    F: Fn(Signal) + Send + 'static,
+{
+    let set = unsafe {
+        let mut set: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut set);
+        for sig in FORWARDED {
+            libc::sigaddset(&mut set, sig);
+        }
+        set
+    };
+    let res = unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &set, ptr::null_mut()) };
+    if res != 0 {
+        return Err(io::Error::from_raw_os_error(res));
+    }
+
+    thread::spawn(move || loop {
+        let mut sig = 0;
+        if unsafe { libc::sigwait(&set, &mut sig) } == 0 {
+            handler(Signal(sig));
+        }
+    });
+    Ok(())
+}
+
+/// Unblock all signals, for use in `pre_exec` as children inherit the
+/// signal mask of rstrtr.
+pub(crate) fn unblock() -> io::Result<()> {
+    let res = unsafe {
+        let mut set: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::pthread_sigmask(libc::SIG_SETMASK, &set, ptr::null_mut())
+    };
+    if res != 0 {
+        return Err(io::Error::from_raw_os_error(res));
+    }
+    Ok(())
+}
@@ -0,0 +1,265 @@
+use std::{
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+
+#[derive(Clone, Default)]
+/// What one of the supervisor's processes is doing, published over the
+/// control socket.
+pub struct Status {
+    pub name: String,
+    pub command: Vec<String>,
+    pub pid: Option<u32>,
+    pub started: Option<SystemTime>,
+    pub restarts: u64,
+    pub last_exit: Option<String>,
+    pub last_restart: Option<SystemTime>,
+    /// Whether the process has passed its readiness probe, if it has one.
+    pub ready: Option<bool>,
+    /// The last `STATUS=` the process sent to its `NOTIFY_SOCKET`.
+    pub notify_status: Option<String>,
+}
+
+impl Status {
+    /// Encode as `key value` lines for the control socket.
+    pub fn to_lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("name {}", self.name)];
+        lines.extend(self.command.iter().map(|arg| format!("command {}", arg)));
+        if let Some(pid) = self.pid {
+            lines.push(format!("pid {}", pid));
+        }
+        if let Some(ready) = self.ready {
+            lines.push(format!("ready {}", ready));
+        }
+        if let Some(notify_status) = &self.notify_status {
+            lines.push(format!("notify_status {}", notify_status));
+        }
+        if let Some(started) = self.started {
+            lines.push(format!("started {}", unix_secs(started)));
+        }
+        lines.push(format!("restarts {}", self.restarts));
+        if let Some(last_exit) = &self.last_exit {
+            lines.push(format!("last_exit {}", last_exit));
+        }
+        if let Some(last_restart) = self.last_restart {
+            lines.push(format!("last_restart {}", unix_secs(last_restart)));
+        }
+        lines
+    }
+
+    /// Decode the statuses of each process from lines produced by
+    /// [`Status::to_lines`].
+    pub fn from_lines(lines: &[String]) -> anyhow::Result<Vec<Self>> {
+        let mut statuses = Vec::new();
+        for line in lines {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            if key == "name" || statuses.is_empty() {
+                statuses.push(Self::default());
+            }
+            let status = statuses.last_mut().unwrap();
+            match key {
+                "name" => status.name = value.to_string(),
+                "command" => status.command.push(value.to_string()),
+                "pid" => status.pid = Some(value.parse()?),
+                "ready" => status.ready = Some(value.parse()?),
+                "notify_status" => status.notify_status = Some(value.to_string()),
+                "started" => status.started = Some(from_unix_secs(value)?),
+                "restarts" => status.restarts = value.parse()?,
+                "last_exit" => status.last_exit = Some(value.to_string()),
+                "last_restart" => status.last_restart = Some(from_unix_secs(value)?),
+                _ => return Err(anyhow!("Unexpected status line {:?}", line)),
+            }
+        }
+        Ok(statuses)
+    }
+
+    pub fn to_json(&self) -> String {
+        let command: Vec<String> = self.command.iter().map(|arg| json_string(arg)).collect();
+        let opt = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+        format!(
+            "{{\"name\":{},\"command\":[{}],\"pid\":{},\"ready\":{},\"notify_status\":{},\"uptime\":{},\"restarts\":{},\"last_exit\":{},\"last_restart\":{}}}",
+            json_string(&self.name),
+            command.join(","),
+            opt(self.pid.map(|pid| pid.to_string())),
+            opt(self.ready.map(|ready| ready.to_string())),
+            opt(self.notify_status.as_deref().map(json_string)),
+            opt(self.uptime().map(|uptime| uptime.as_secs_f64().to_string())),
+            self.restarts,
+            opt(self.last_exit.as_deref().map(json_string)),
+            opt(self.last_restart.map(unix_secs)),
+        )
+    }
+
+    fn uptime(&self) -> Option<Duration> {
+        self.started
+            .map(|started| started.elapsed().unwrap_or_default())
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Command:      {}", self.command.join(" "))?;
+        match self.pid {
+            Some(pid) => writeln!(f, "PID:          {}", pid)?,
+            None => writeln!(f, "PID:          not running")?,
+        }
+        if let Some(ready) = self.ready {
+            writeln!(f, "Ready:        {}", if ready { "yes" } else { "no" })?;
+        }
+        if let Some(notify_status) = &self.notify_status {
+            writeln!(f, "Status:       {}", notify_status)?;
+        }
+        if let Some(uptime) = self.uptime() {
+            writeln!(f, "Uptime:       {}", format_duration(uptime))?;
+        }
+        writeln!(f, "Restarts:     {}", self.restarts)?;
+        if let Some(last_exit) = &self.last_exit {
+            writeln!(f, "Last exit:    {}", last_exit)?;
+        }
+        if let Some(last_restart) = self.last_restart {
+            let ago = last_restart.elapsed().unwrap_or_default();
+            writeln!(f, "Last restart: {} ago", format_duration(ago))?;
+        }
+        Ok(())
+    }
+}
+
+fn unix_secs(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+        .to_string()
+}
+
+fn from_unix_secs(s: &str) -> anyhow::Result<SystemTime> {
+    Ok(UNIX_EPOCH + Duration::from_secs_f64(s.parse()?))
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs / 60 % 60),
+    }
+}
+
+/// Quote `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: f64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs_f64(secs)
+    }
+
+    #[test]
+    fn lines() {
+        let statuses = [
+            Status {
+                name: "web".to_string(),
+                command: vec![
+                    "sh".to_string(),
+                    "-c".to_string(),
+                    "exec web --port 80".to_string(),
+                ],
+                pid: Some(1234),
+                started: Some(secs(1_700_000_000.5)),
+                restarts: 2,
+                last_exit: Some("exit status: 1".to_string()),
+                last_restart: Some(secs(1_699_999_990.25)),
+                ready: Some(true),
+                notify_status: Some("Serving 3 requests".to_string()),
+            },
+            Status {
+                name: "worker".to_string(),
+                command: vec!["worker".to_string(), "".to_string()],
+                ..Status::default()
+            },
+        ];
+        let lines: Vec<String> = statuses.iter().flat_map(Status::to_lines).collect();
+        let decoded = Status::from_lines(&lines).unwrap();
+        assert_eq!(decoded.len(), 2);
+
+        let web = &decoded[0];
+        assert_eq!(web.name, "web");
+        assert_eq!(web.command, ["sh", "-c", "exec web --port 80"]);
+        assert_eq!(web.pid, Some(1234));
+        assert_eq!(web.started, Some(secs(1_700_000_000.5)));
+        assert_eq!(web.restarts, 2);
+        assert_eq!(web.last_exit.as_deref(), Some("exit status: 1"));
+        assert_eq!(web.last_restart, Some(secs(1_699_999_990.25)));
+        assert_eq!(web.ready, Some(true));
+        assert_eq!(web.notify_status.as_deref(), Some("Serving 3 requests"));
+
+        let worker = &decoded[1];
+        assert_eq!(worker.name, "worker");
+        assert_eq!(worker.command, ["worker", ""]);
+        assert_eq!(worker.pid, None);
+        assert_eq!(worker.started, None);
+        assert_eq!(worker.restarts, 0);
+        assert_eq!(worker.last_exit, None);
+        assert_eq!(worker.last_restart, None);
+        assert_eq!(worker.ready, None);
+        assert_eq!(worker.notify_status, None);
+
+        let encoded: Vec<String> = decoded.iter().flat_map(Status::to_lines).collect();
+        assert_eq!(encoded, lines);
+
+        assert!(Status::from_lines(&["bogus 1".to_string()]).is_err());
+        assert!(Status::from_lines(&["pid x".to_string()]).is_err());
+    }
+
+    #[test]
+    fn json() {
+        let status = Status {
+            name: "web".to_string(),
+            command: vec![
+                "echo".to_string(),
+                "say \"hi\"\\".to_string(),
+                "a\nb\tc\r\u{1}\u{1f}é".to_string(),
+            ],
+            pid: Some(1234),
+            restarts: 1,
+            last_exit: Some("signal: 9 (SIGKILL)".to_string()),
+            last_restart: Some(secs(1.5)),
+            ready: Some(false),
+            notify_status: Some("\"busy\"\n\u{0}".to_string()),
+            ..Status::default()
+        };
+        assert_eq!(
+            status.to_json(),
+            concat!(
+                r#"{"name":"web","#,
+                r#""command":["echo","say \"hi\"\\","a\nb\tc\r\u0001\u001fé"],"#,
+                r#""pid":1234,"ready":false,"notify_status":"\"busy\"\n\u0000","#,
+                r#""uptime":null,"restarts":1,"last_exit":"signal: 9 (SIGKILL)","#,
+                r#""last_restart":1.5}"#,
+            )
+        );
+        assert_eq!(
+            Status::default().to_json(),
+            r#"{"name":"","command":[],"pid":null,"ready":null,"notify_status":null,"uptime":null,"restarts":0,"last_exit":null,"last_restart":null}"#
+        );
+    }
+}
@@ -0,0 +1,2014 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    env,
+    fmt::Display,
+    fs, io, mem,
+    os::unix::{
+        io::{AsRawFd, OwnedFd},
+        process::{CommandExt, ExitStatusExt},
+    },
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{anyhow, bail};
+use clap::ArgEnum;
+use notify::{watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{
+    backoff::Backoff,
+    control::{self, Reply, Request},
+    filter::Filter,
+    handle::{Handle, Lifecycle, Subscribers},
+    listen::{self, Listen},
+    lock::Lock,
+    log_file::{self, LogFile},
+    output::{ColorMode, Logs, Output, OutputMode},
+    probe::{self, Probe},
+    process::{program_name, Process},
+    proxy::{Gate, Proxy},
+    sd_notify::{Notification, NotifySocket},
+    signal::{self, Signal},
+    status::Status,
+};
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Always restart the command when it exits.
+    Always,
+    /// Restart the command only when it exits unsuccessfully.
+    OnFailure,
+    /// An alias of `Always`, requested stops are never restarted anyway.
+    UnlessStopped,
+    /// Never restart the command, quit when it exits.
+    Never,
+}
+
+impl RestartPolicy {
+    fn should_restart(self, exit: ExitStatus) -> bool {
+        match self {
+            // Requested stops never get here.
+            RestartPolicy::Always | RestartPolicy::UnlessStopped => true,
+            RestartPolicy::OnFailure => !exit.success(),
+            RestartPolicy::Never => false,
+        }
+    }
+}
+
+/// How long each readiness check may take.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Everything the supervisor reacts to arrives on one channel.
+pub(crate) enum Event {
+    Fs(DebouncedEvent),
+    Request(Request, Reply),
+    Signal(Signal),
+    /// The command with this pid exited and is waiting to be reaped.
+    Exited(u32),
+    /// The command with this pid passed its readiness probe.
+    Ready(u32),
+    /// The command with this pid failed its liveness probe, and why.
+    Unhealthy(u32, String),
+    /// The command with this pid sent a message to its `NOTIFY_SOCKET`.
+    Notified(u32, Notification),
+    /// The proxied connections to the command with this pid have closed.
+    Drained(u32),
+    /// The build command with this pid exited.
+    Built(u32, io::Result<ExitStatus>),
+}
+
+/// What to do, to the named process or to all of them.
+enum Control {
+    /// Answer once restarted, or once ready if waiting.
+    Restart(Option<String>, Option<Reply>, bool),
+    Quit(Option<String>, Option<Reply>),
+    Changed(PathBuf),
+    Signal(Signal),
+}
+
+/// A restart asked for, made once built.
+struct Restart {
+    name: Option<String>,
+    reply: Option<Reply>,
+    wait: bool,
+}
+
+/// The build command running before restarting.
+struct Build {
+    pid: u32,
+    restarts: Vec<Restart>,
+    /// Asked for since the build started, so built again for.
+    next: Vec<Restart>,
+}
+
+/// What to do once the command has stopped.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Then {
+    Restart,
+    Quit,
+    /// Quit with the command's exit status, after forwarding a signal.
+    Forward,
+    /// Go back to the previous command, after its replacement failed.
+    Revert,
+}
+
+enum State {
+    Running,
+    /// Waiting for proxied connections to close before sending `signal`,
+    /// which is sent anyway at `deadline`.
+    Draining {
+        signal: Signal,
+        deadline: Instant,
+        then: Then,
+    },
+    /// The command was sent `signal`, SIGKILL follows at `deadline`.
+    Stopping {
+        signal: Signal,
+        deadline: Option<Instant>,
+        then: Then,
+    },
+    /// The command exited by itself and is restarted at `until`.
+    Waiting {
+        until: Instant,
+    },
+    /// The command won't be restarted unless asked to, rstrtr quits with
+    /// the first non-zero `code` once every process is done.
+    Done {
+        code: i32,
+    },
+}
+
+/// Configures a [`Supervisor`], see [`Supervisor::builder`].
+pub struct SupervisorBuilder {
+    processes: Vec<Process>,
+    env: Vec<(String, String)>,
+    current_dir: Option<PathBuf>,
+    control: Option<PathBuf>,
+    restart: RestartPolicy,
+    max_restarts: Option<usize>,
+    restart_window: Duration,
+    watch: Vec<PathBuf>,
+    ignore: Vec<String>,
+    include: Vec<String>,
+    vcs_ignore: bool,
+    build: Option<String>,
+    backoff_initial: Duration,
+    backoff_multiplier: f64,
+    backoff_max: Duration,
+    backoff_reset: Duration,
+    stop_signal: Signal,
+    stop_timeout: Duration,
+    process_group: bool,
+    forward_signals: bool,
+    output: OutputMode,
+    color: ColorMode,
+    log_file: Option<PathBuf>,
+    stderr_log_file: Option<PathBuf>,
+    log_max_size: u64,
+    log_max_files: usize,
+    ready: Option<Probe>,
+    ready_timeout: Duration,
+    probe_interval: Duration,
+    liveness: Option<Probe>,
+    liveness_interval: Duration,
+    liveness_timeout: Duration,
+    liveness_failures: usize,
+    watchdog: Option<Duration>,
+    listen: Vec<Listen>,
+    overlap: bool,
+    proxy: Vec<Proxy>,
+    proxy_timeout: Duration,
+    drain_timeout: Duration,
+    quiet: bool,
+}
+
+impl SupervisorBuilder {
+    fn new(processes: Vec<Process>) -> Self {
+        Self {
+            processes,
+            env: Vec::new(),
+            current_dir: None,
+            control: None,
+            restart: RestartPolicy::Always,
+            max_restarts: None,
+            restart_window: Duration::from_secs(60),
+            watch: Vec::new(),
+            ignore: Vec::new(),
+            include: Vec::new(),
+            vcs_ignore: true,
+            build: None,
+            backoff_initial: Duration::from_millis(100),
+            backoff_multiplier: 2.0,
+            backoff_max: Duration::from_secs(30),
+            backoff_reset: Duration::from_secs(10),
+            stop_signal: Signal::TERM,
+            stop_timeout: Duration::from_secs(10),
+            process_group: true,
+            forward_signals: false,
+            output: OutputMode::Auto,
+            color: ColorMode::Auto,
+            log_file: None,
+            stderr_log_file: None,
+            log_max_size: 10 * 1024 * 1024,
+            log_max_files: 5,
+            ready: None,
+            ready_timeout: Duration::from_secs(30),
+            probe_interval: Duration::from_millis(250),
+            liveness: None,
+            liveness_interval: Duration::from_secs(10),
+            liveness_timeout: Duration::from_secs(1),
+            liveness_failures: 3,
+            watchdog: None,
+            listen: Vec::new(),
+            overlap: false,
+            proxy: Vec::new(),
+            proxy_timeout: Duration::from_secs(30),
+            drain_timeout: Duration::from_secs(5),
+            quiet: false,
+        }
+    }
+
+    /// Set an environment variable for every process and the build
+    /// command.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Run processes and the build command in `dir`, unless set for the
+    /// process.
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    /// Control file path, the control socket and lock live beside it.
+    /// Without one the supervisor is only controlled through its [`Handle`].
+    pub fn control_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.control = Some(path.into());
+        self
+    }
+
+    /// When to restart processes after they exit by themselves, unless set
+    /// for the process.
+    pub fn restart_policy(mut self, restart: RestartPolicy) -> Self {
+        self.restart = restart;
+        self
+    }
+
+    /// Give up after the command exits `max` times within `window`.
+    pub fn max_restarts(mut self, max: usize, window: Duration) -> Self {
+        self.max_restarts = Some(max);
+        self.restart_window = window;
+        self
+    }
+
+    /// Restart the command when files under `path` change.
+    pub fn watch(mut self, path: impl Into<PathBuf>) -> Self {
+        self.watch.push(path.into());
+        self
+    }
+
+    /// Ignore changes to watched paths matching `glob`.
+    pub fn ignore(mut self, glob: impl Into<String>) -> Self {
+        self.ignore.push(glob.into());
+        self
+    }
+
+    /// Only restart for changes to watched paths matching `glob`.
+    pub fn include(mut self, glob: impl Into<String>) -> Self {
+        self.include.push(glob.into());
+        self
+    }
+
+    /// Honor `.gitignore` and `.ignore` files in watched paths, on by
+    /// default.
+    pub fn vcs_ignore(mut self, vcs_ignore: bool) -> Self {
+        self.vcs_ignore = vcs_ignore;
+        self
+    }
+
+    /// Shell command run before a requested restart, the running command
+    /// is kept if it fails.
+    pub fn build_command(mut self, build: impl Into<String>) -> Self {
+        self.build = Some(build.into());
+        self
+    }
+
+    /// Delay before restarting a command that exited by itself, growing by
+    /// `multiplier` up to `max` until it stays up for `reset`.
+    pub fn backoff(
+        mut self,
+        initial: Duration,
+        multiplier: f64,
+        max: Duration,
+        reset: Duration,
+    ) -> Self {
+        self.backoff_initial = initial;
+        self.backoff_multiplier = multiplier;
+        self.backoff_max = max;
+        self.backoff_reset = reset;
+        self
+    }
+
+    /// Stop the command with `signal`, sending SIGKILL if it hasn't exited
+    /// after `timeout`.
+    pub fn stop(mut self, signal: Signal, timeout: Duration) -> Self {
+        self.stop_signal = signal;
+        self.stop_timeout = timeout;
+        self
+    }
+
+    /// Run the command in its own process group and signal the whole
+    /// group, on by default.
+    pub fn process_group(mut self, process_group: bool) -> Self {
+        self.process_group = process_group;
+        self
+    }
+
+    /// Forward SIGINT, SIGTERM and SIGHUP to the command and quit. This
+    /// blocks those signals in the calling thread and threads it spawns.
+    pub fn forward_signals(mut self, forward_signals: bool) -> Self {
+        self.forward_signals = forward_signals;
+        self
+    }
+
+    /// Whether to pipe the processes' output through rstrtr, prefixing each
+    /// line with a timestamp and the process name.
+    pub fn output(mut self, output: OutputMode) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Whether to color prefixed output.
+    pub fn color(mut self, color: ColorMode) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Also write output and lifecycle messages to `path`. The processes'
+    /// output is piped through rstrtr even if raw.
+    pub fn log_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.log_file = Some(path.into());
+        self
+    }
+
+    /// Write the processes' stderr to `path` rather than the log file.
+    pub fn stderr_log_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.stderr_log_file = Some(path.into());
+        self
+    }
+
+    /// Rotate log files once they reach `max_size` bytes, keeping
+    /// `max_files` old ones.
+    pub fn log_rotation(mut self, max_size: u64, max_files: usize) -> Self {
+        self.log_max_size = max_size;
+        self.log_max_files = max_files;
+        self
+    }
+
+    /// How to tell processes are ready once started, unless set for the
+    /// process.
+    pub fn ready_probe(mut self, probe: Probe) -> Self {
+        self.ready = Some(probe);
+        self
+    }
+
+    /// Give up waiting for a process to be ready after `timeout`, checking
+    /// every `interval`.
+    pub fn ready_timeout(mut self, timeout: Duration, interval: Duration) -> Self {
+        self.ready_timeout = timeout;
+        self.probe_interval = interval;
+        self
+    }
+
+    /// How to tell processes are still working once ready, unless set for
+    /// the process. Output probes can't be used.
+    pub fn liveness_probe(mut self, probe: Probe) -> Self {
+        self.liveness = Some(probe);
+        self
+    }
+
+    /// Check liveness every `interval`, each check failing after `timeout`,
+    /// and restart a process once it fails `failures` checks in a row.
+    pub fn liveness(mut self, interval: Duration, timeout: Duration, failures: usize) -> Self {
+        self.liveness_interval = interval;
+        self.liveness_timeout = timeout;
+        self.liveness_failures = failures;
+        self
+    }
+
+    /// Set `WATCHDOG_USEC` for processes, restarting them once ready if they
+    /// don't send `WATCHDOG=1` to their `NOTIFY_SOCKET` within `timeout`.
+    pub fn watchdog(mut self, timeout: Duration) -> Self {
+        self.watchdog = Some(timeout);
+        self
+    }
+
+    /// Pass processes a socket listening on `listen`, unless they have
+    /// their own. It is bound once and kept open across restarts.
+    pub fn listen(mut self, listen: Listen) -> Self {
+        self.listen.push(listen);
+        self
+    }
+
+    /// Start a process's replacement before stopping it on restart,
+    /// stopping it once the replacement is ready or keeping it if the
+    /// replacement isn't. Its ready probe must tell the replacement apart
+    /// from the process, so tcp and http probes can't be used.
+    pub fn overlap(mut self, overlap: bool) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    /// Forward connections to processes through `proxy`, unless they have
+    /// their own. Connections are held while a process is down and drained
+    /// before it's stopped.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy.push(proxy);
+        self
+    }
+
+    /// Give up on connections held for longer than `hold`, and stop
+    /// processes anyway once connections have been draining for `drain`.
+    pub fn proxy_timeouts(mut self, hold: Duration, drain: Duration) -> Self {
+        self.proxy_timeout = hold;
+        self.drain_timeout = drain;
+        self
+    }
+
+    /// Don't print lifecycle messages.
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Take over the control path and start listening for events, the
+    /// processes are started by [`Supervisor::run`].
+    pub fn build(mut self) -> anyhow::Result<Supervisor> {
+        if self.processes.is_empty() {
+            bail!("No command to run");
+        }
+        let mut names = HashSet::new();
+        for process in &self.processes {
+            if process.command.is_empty() {
+                bail!("No command to run for {}", process.name);
+            }
+            if !names.insert(&process.name) {
+                bail!("Duplicate process name {}", process.name);
+            }
+            match process.liveness.as_ref().or(self.liveness.as_ref()) {
+                Some(Probe::Output(_)) => bail!("Output probes can't check liveness"),
+                Some(Probe::Notify) => bail!("Notify probes can't check liveness, use a watchdog"),
+                _ => {}
+            }
+            // The previous child would answer these on the shared port.
+            if let (true, Some(Probe::Tcp(_) | Probe::Http { .. })) =
+                (self.overlap, process.ready.as_ref().or(self.ready.as_ref()))
+            {
+                bail!("Overlapping restarts can't use tcp or http ready probes, the previous process would pass them");
+            }
+        }
+
+        // Before anything another rstrtr for the same control file would
+        // notice, such as binding its sockets or writing its logs.
+        let lock = self.control.as_deref().map(Lock::acquire).transpose()?;
+
+        let (tx, rx) = channel();
+        let status = Arc::new(Mutex::new(
+            self.processes
+                .iter()
+                .map(|process| Status {
+                    name: process.name.clone(),
+                    command: process.command.clone(),
+                    ..Status::default()
+                })
+                .collect(),
+        ));
+        if self.forward_signals {
+            let signals = tx.clone();
+            signal::handle(move |signal| {
+                let _ = signals.send(Event::Signal(signal));
+            })?;
+        }
+
+        let (fs_tx, fs_rx) = channel();
+        let fs_events = tx.clone();
+        thread::spawn(move || {
+            for msg in fs_rx {
+                if fs_events.send(Event::Fs(msg)).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut watcher = watcher(fs_tx, Duration::from_millis(100))?;
+        let roots = self
+            .watch
+            .iter()
+            .map(|path| path.canonicalize())
+            .collect::<io::Result<Vec<_>>>()?;
+        for root in &roots {
+            watcher.watch(root, RecursiveMode::Recursive)?;
+        }
+        let mut filter = Filter::new(&roots, &self.ignore, &self.include, self.vcs_ignore)?;
+
+        let names: Vec<String> = self.processes.iter().map(|p| p.name.clone()).collect();
+        let prefixed = match self.output {
+            OutputMode::Auto => names.len() > 1,
+            OutputMode::Raw => false,
+            OutputMode::Prefixed => true,
+        };
+        let color = match self.color {
+            ColorMode::Auto => env::var_os("NO_COLOR").is_none() && atty::is(atty::Stream::Stdout),
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        };
+        let open = |path: &PathBuf| {
+            LogFile::open(path, self.log_max_size, self.log_max_files)
+                .map_err(|e| anyhow!("Error opening log file {}: {}", path.display(), e))
+        };
+        let logs = match &self.log_file {
+            Some(path) => Some(Logs {
+                stdout: open(path)?,
+                stderr: self.stderr_log_file.as_ref().map(open).transpose()?,
+            }),
+            None if self.stderr_log_file.is_some() => bail!("A stderr log file needs a log file"),
+            None => None,
+        };
+        // Writing to the logs mustn't look like a change to restart for.
+        for path in self.log_file.iter().chain(&self.stderr_log_file) {
+            let path = path.canonicalize()?;
+            for n in 1..=self.log_max_files {
+                filter.ignore_path(log_file::rotated(&path, n));
+            }
+            filter.ignore_path(path);
+        }
+        let matched = self.processes.iter().any(|process| {
+            matches!(
+                process.ready.as_ref().or(self.ready.as_ref()),
+                Some(Probe::Output(_))
+            )
+        });
+        let http = self.processes.iter().any(|process| {
+            match process.proxy.is_empty() {
+                true => &self.proxy,
+                false => &process.proxy,
+            }
+            .iter()
+            .any(Proxy::is_http)
+        });
+        let output = Arc::new(Output::new(&names, prefixed, matched || http, color, logs));
+
+        let mut sockets: Vec<(Listen, Arc<OwnedFd>)> = Vec::new();
+        let mut bound = Vec::new();
+        for process in &self.processes {
+            let listen = match process.listen.is_empty() {
+                true => &self.listen,
+                false => &process.listen,
+            };
+            let mut fds = Vec::new();
+            for listen in listen {
+                let fd = match sockets.iter().find(|(l, _)| l == listen) {
+                    Some((_, fd)) => fd.clone(),
+                    None => {
+                        let fd = Arc::new(listen.bind()?);
+                        sockets.push((listen.clone(), fd.clone()));
+                        fd
+                    }
+                };
+                fds.push(fd);
+            }
+            bound.push(fds);
+        }
+        // Nor binding unix sockets in a watched directory.
+        for (listen, _) in &sockets {
+            if let Listen::Unix(path) = listen {
+                filter.ignore_path(path.canonicalize()?);
+            }
+        }
+        let mut gates = Vec::new();
+        for process in &self.processes {
+            let proxies = match process.proxy.is_empty() {
+                true => &self.proxy,
+                false => &process.proxy,
+            };
+            if proxies.is_empty() {
+                gates.push(None);
+                continue;
+            }
+            let gate = Arc::new(Gate::new(&process.name));
+            for proxy in proxies {
+                proxy.serve(proxy.bind()?, gate.clone(), self.proxy_timeout);
+            }
+            gates.push(Some(gate));
+        }
+
+        let procs = mem::take(&mut self.processes)
+            .into_iter()
+            .zip(bound)
+            .zip(gates)
+            .map(|((process, sockets), gate)| Supervised {
+                restart: process.restart.unwrap_or(self.restart),
+                ready: process.ready.clone().or_else(|| self.ready.clone()),
+                liveness: process.liveness.clone().or_else(|| self.liveness.clone()),
+                process,
+                backoff: Backoff::new(
+                    self.backoff_initial,
+                    self.backoff_multiplier,
+                    self.backoff_max,
+                    self.backoff_reset,
+                ),
+                exits: VecDeque::new(),
+                replies: Vec::new(),
+                wait: Vec::new(),
+                waiters: Vec::new(),
+                probe: None,
+                ready_deadline: None,
+                live: None,
+                notify: None,
+                watchdog: None,
+                sockets,
+                gate,
+                previous: None,
+                child: None,
+                readers: Vec::new(),
+                started: Instant::now(),
+                state: State::Running,
+            })
+            .collect();
+        let mut supervisor = Supervisor {
+            handle: Handle {
+                tx,
+                status,
+                subscribers: Subscribers::default(),
+            },
+            rx,
+            lock: None,
+            control: None,
+            socket: None,
+            filter,
+            _watcher: watcher,
+            output,
+            procs,
+            sockets: sockets.into_iter().map(|(listen, _)| listen).collect(),
+            replies: Vec::new(),
+            build: None,
+            opts: self,
+        };
+        if let (Some(rstrtr), Some((lock, stale))) = (supervisor.opts.control.clone(), lock) {
+            supervisor.take_control(&rstrtr, lock, stale)?;
+        }
+        Ok(supervisor)
+    }
+}
+
+/// One of the supervisor's processes and what it is doing.
+struct Supervised {
+    process: Process,
+    restart: RestartPolicy,
+    backoff: Backoff,
+    ready: Option<Probe>,
+    exits: VecDeque<Instant>,
+    /// Answered once running again.
+    replies: Vec<Reply>,
+    /// Moved to `waiters` once running again.
+    wait: Vec<Reply>,
+    /// Answered once the child is ready.
+    waiters: Vec<Reply>,
+    /// Stops polling the readiness probe when dropped.
+    probe: Option<Sender<()>>,
+    /// Set until the child is ready, or given up on.
+    ready_deadline: Option<Instant>,
+    liveness: Option<Probe>,
+    /// Stops checking the child's liveness when dropped.
+    live: Option<Sender<()>>,
+    /// Where the child sends `sd_notify` messages.
+    notify: Option<NotifySocket>,
+    /// Set once the child is ready, it must send `WATCHDOG=1` by then.
+    watchdog: Option<Instant>,
+    /// Listening sockets passed to the child.
+    sockets: Vec<Arc<OwnedFd>>,
+    /// Lets proxied connections through to the child.
+    gate: Option<Arc<Gate>>,
+    /// The child being replaced by an overlapping restart.
+    previous: Option<Previous>,
+    child: Option<Child>,
+    /// Disconnect once the child's piped output has been written.
+    readers: Vec<Receiver<()>>,
+    started: Instant,
+    state: State,
+}
+
+/// A child kept running until its replacement is ready.
+struct Previous {
+    child: Child,
+    readers: Vec<Receiver<()>>,
+    notify: Option<NotifySocket>,
+    started: Instant,
+    ready: Option<bool>,
+    notify_status: Option<String>,
+    /// Set once stopping, SIGKILL follows at `deadline`.
+    stopping: bool,
+    deadline: Option<Instant>,
+    /// Sent once proxied connections have drained, or at `deadline`.
+    signal: Option<Signal>,
+}
+
+impl Supervised {
+    fn deadline(&self) -> Option<Instant> {
+        let deadline = match self.state {
+            State::Running => self.ready_deadline.into_iter().chain(self.watchdog).min(),
+            State::Done { .. } => None,
+            State::Draining { deadline, .. } => Some(deadline),
+            State::Stopping { deadline, .. } => deadline,
+            State::Waiting { until } => Some(until),
+        };
+        match &self.previous {
+            Some(previous) => deadline.into_iter().chain(previous.deadline).min(),
+            None => deadline,
+        }
+    }
+
+    fn pid(&self) -> u32 {
+        self.child.as_ref().unwrap().id()
+    }
+}
+
+/// Runs the processes and restarts them, driven entirely by [`Event`]s so
+/// it sleeps until something happens.
+pub struct Supervisor {
+    opts: SupervisorBuilder,
+    handle: Handle,
+    rx: Receiver<Event>,
+    lock: Option<Lock>,
+    control: Option<PathBuf>,
+    socket: Option<PathBuf>,
+    filter: Filter,
+    _watcher: RecommendedWatcher,
+    output: Arc<Output>,
+    procs: Vec<Supervised>,
+    /// Where the processes' sockets listen, kept open by `procs`.
+    sockets: Vec<Listen>,
+    /// Answered once every process has quit.
+    replies: Vec<Reply>,
+    build: Option<Build>,
+}
+
+impl Supervisor {
+    /// Start configuring a supervisor for `command`, a program followed by
+    /// its arguments.
+    pub fn builder<I, S>(command: I) -> SupervisorBuilder
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let command: Vec<String> = command.into_iter().map(Into::into).collect();
+        SupervisorBuilder::new(vec![Process::new(program_name(&command), command)])
+    }
+
+    /// Start configuring a supervisor for several named processes.
+    pub fn with_processes(processes: impl IntoIterator<Item = Process>) -> SupervisorBuilder {
+        SupervisorBuilder::new(processes.into_iter().collect())
+    }
+
+    /// A handle for controlling the supervisor once it is running.
+    pub fn handle(&self) -> Handle {
+        self.handle.clone()
+    }
+
+    /// Listen on the control file `rstrtr` and its socket, once `lock`ed.
+    fn take_control(
+        &mut self,
+        rstrtr: &Path,
+        lock: Lock,
+        stale: Option<u32>,
+    ) -> anyhow::Result<()> {
+        if let Some(pid) = stale {
+            self.log(format_args!("Cleaning up after crashed rstrtr pid {}", pid));
+            let _ = fs::remove_file(rstrtr);
+        }
+
+        let requests = self.handle.tx.clone();
+        let socket = control::socket_path(rstrtr);
+        let published = self.handle.status.clone();
+        let output = self.output.clone();
+        control::listen(&socket, move |request, reply| match request {
+            Request::Logs { lines, follow } => output.tail(lines, follow, reply),
+            Request::Status => {
+                let lines: Vec<String> = published
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .flat_map(Status::to_lines)
+                    .collect();
+                reply.send_lines(&lines);
+            }
+            request => {
+                let _ = requests.send(Event::Request(request, reply));
+            }
+        })?;
+        let socket = socket.canonicalize()?;
+
+        fs::write(rstrtr, "\n")?;
+        let control = rstrtr.canonicalize()?;
+        self._watcher.watch(&control, RecursiveMode::NonRecursive)?;
+
+        self.filter.ignore_path(control.clone());
+        self.filter.ignore_path(socket.clone());
+        self.filter.ignore_path(lock.path().canonicalize()?);
+        self.lock = Some(lock);
+        self.control = Some(control);
+        self.socket = Some(socket);
+        Ok(())
+    }
+
+    /// Run the processes until told to quit or none are left running,
+    /// returning rstrtr's exit code.
+    pub fn run(mut self) -> i32 {
+        for i in 0..self.procs.len() {
+            self.spawn(i);
+        }
+        while !self.done() {
+            let event = match self.deadline() {
+                Some(deadline) => {
+                    match self
+                        .rx
+                        .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                    {
+                        Ok(event) => Some(event),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => unreachable!(),
+                    }
+                }
+                None => Some(self.rx.recv().unwrap()),
+            };
+            match event {
+                Some(Event::Exited(pid)) => self.exited(pid),
+                Some(Event::Ready(pid)) => self.ready(pid),
+                Some(Event::Unhealthy(pid, reason)) => self.unhealthy(pid, reason),
+                Some(Event::Notified(pid, notification)) => self.notified(pid, notification),
+                Some(Event::Drained(pid)) => self.drained(pid),
+                Some(Event::Built(pid, exit)) => self.built(pid, exit),
+                Some(event) => {
+                    if let Some(control) = self.control(event) {
+                        self.apply(control);
+                    }
+                }
+                None => {}
+            }
+            self.expire();
+        }
+
+        let code = self.exit_code();
+        self.log("Quitting...");
+        self.emit(Lifecycle::Quit { code });
+        if let Some(control) = &self.control {
+            let _ = fs::remove_file(control);
+        }
+        if let Some(socket) = &self.socket {
+            let _ = fs::remove_file(socket);
+        }
+        for listen in &self.sockets {
+            if let Listen::Unix(path) = listen {
+                let _ = fs::remove_file(path);
+            }
+        }
+        self.lock.take();
+        self.output.close();
+        for reply in self.replies.drain(..) {
+            reply.send(Ok(()));
+        }
+        code
+    }
+
+    fn log(&self, msg: impl Display) {
+        if !self.opts.quiet {
+            self.output.message(msg);
+        }
+    }
+
+    /// Log `msg` about process `i`, naming it if there are several.
+    fn log_process(&self, i: usize, msg: impl Display) {
+        if self.procs.len() > 1 {
+            self.log(format_args!("{}: {}", self.procs[i].process.name, msg));
+        } else {
+            self.log(msg);
+        }
+    }
+
+    fn emit(&self, event: Lifecycle) {
+        self.handle
+            .subscribers
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(event.clone()).is_ok());
+    }
+
+    fn name(&self, i: usize) -> String {
+        self.procs[i].process.name.clone()
+    }
+
+    fn done(&self) -> bool {
+        self.build.is_none()
+            && self
+                .procs
+                .iter()
+                .all(|p| matches!(p.state, State::Done { .. }) && p.previous.is_none())
+    }
+
+    /// The first non-zero exit code of the processes.
+    fn exit_code(&self) -> i32 {
+        self.procs
+            .iter()
+            .filter_map(|p| match p.state {
+                State::Done { code } if code != 0 => Some(code),
+                _ => None,
+            })
+            .next()
+            .unwrap_or(0)
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.procs.iter().filter_map(Supervised::deadline).min()
+    }
+
+    /// The processes named `name`, or all of them.
+    fn targets(&self, name: Option<&str>) -> Result<Vec<usize>, String> {
+        match name {
+            Some(name) => self
+                .procs
+                .iter()
+                .position(|p| p.process.name == name)
+                .map(|i| vec![i])
+                .ok_or_else(|| format!("No process named {}", name)),
+            None => Ok((0..self.procs.len()).collect()),
+        }
+    }
+
+    fn control(&self, event: Event) -> Option<Control> {
+        let msg = match event {
+            Event::Fs(msg) => msg,
+            Event::Request(Request::Restart { name, wait }, reply) => {
+                return Some(Control::Restart(name, Some(reply), wait))
+            }
+            Event::Request(Request::Quit(name), reply) => {
+                return Some(Control::Quit(name, Some(reply)))
+            }
+            Event::Request(Request::Status | Request::Logs { .. }, _)
+            | Event::Exited(_)
+            | Event::Ready(_)
+            | Event::Unhealthy(..)
+            | Event::Notified(..)
+            | Event::Drained(_)
+            | Event::Built(..) => return None,
+            Event::Signal(signal) => return Some(Control::Signal(signal)),
+        };
+        let control = self.control.as_deref();
+        match msg {
+            DebouncedEvent::Write(path) if Some(path.as_path()) == control => {
+                Some(Control::Restart(None, None, false))
+            }
+            DebouncedEvent::Remove(path) if Some(path.as_path()) == control => {
+                Some(Control::Quit(None, None))
+            }
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Remove(path)
+                if !self.filter.is_ignored(&path) =>
+            {
+                Some(Control::Changed(path))
+            }
+            DebouncedEvent::Rename(from, to) => {
+                if !self.filter.is_ignored(&to) {
+                    Some(Control::Changed(to))
+                } else if !self.filter.is_ignored(&from) {
+                    Some(Control::Changed(from))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn apply(&mut self, control: Control) {
+        match control {
+            Control::Restart(name, reply, wait) => self.restart(name.as_deref(), reply, wait, true),
+            Control::Changed(path) => {
+                self.log(format_args!("Changed {}", path.display()));
+                self.emit(Lifecycle::Changed(path));
+                self.restart(None, None, false, true);
+            }
+            Control::Quit(name, reply) => {
+                let targets = match self.targets(name.as_deref()) {
+                    Ok(targets) => targets,
+                    Err(e) => {
+                        if let Some(reply) = reply {
+                            reply.send(Err(e));
+                        }
+                        return;
+                    }
+                };
+                match name {
+                    Some(_) => self.procs[targets[0]].replies.extend(reply),
+                    None => {
+                        self.replies.extend(reply);
+                        self.cancel_build(self.opts.stop_signal);
+                    }
+                }
+                for i in targets {
+                    self.stop(i, self.opts.stop_signal, Then::Quit);
+                }
+            }
+            Control::Signal(signal) => {
+                self.log(format_args!("Received {}", signal));
+                self.cancel_build(signal);
+                for i in 0..self.procs.len() {
+                    self.stop(i, signal, Then::Forward);
+                }
+            }
+        }
+    }
+
+    /// Restart the named process or all of them, building first if `build`,
+    /// answering `reply` once they are running again, or ready if `wait`,
+    /// or once the build failed.
+    fn restart(&mut self, name: Option<&str>, reply: Option<Reply>, wait: bool, build: bool) {
+        let targets = match self.targets(name) {
+            Ok(targets) => targets,
+            Err(e) => {
+                if let Some(reply) = reply {
+                    reply.send(Err(e));
+                }
+                return;
+            }
+        };
+        let stopping = |i: &usize, then: &[Then]| matches!(self.procs[*i].state, State::Draining { then: t, .. } | State::Stopping { then: t, .. } if then.contains(&t));
+        if targets
+            .iter()
+            .any(|i| stopping(i, &[Then::Quit, Then::Forward]))
+        {
+            if let Some(reply) = reply {
+                reply.send(Err("Quitting".to_string()));
+            }
+            return;
+        }
+
+        if build
+            && self.opts.build.is_some()
+            && !targets.iter().all(|i| stopping(i, &[Then::Restart]))
+        {
+            let restart = Restart {
+                name: name.map(str::to_string),
+                reply,
+                wait,
+            };
+            match &mut self.build {
+                Some(build) => build.next.push(restart),
+                None => self.start_build(vec![restart]),
+            }
+            return;
+        }
+        let mut replies = match reply {
+            Some(reply) => reply.split(targets.len()),
+            None => Vec::new(),
+        }
+        .into_iter();
+        for i in targets {
+            let p = &mut self.procs[i];
+            match wait {
+                true => p.wait.extend(replies.next()),
+                false => p.replies.extend(replies.next()),
+            }
+            match p.state {
+                State::Draining { .. } | State::Stopping { .. } => {}
+                State::Running if self.opts.overlap && p.previous.is_none() => self.replace(i),
+                State::Running => self.stop(i, self.opts.stop_signal, Then::Restart),
+                State::Waiting { .. } | State::Done { .. } => {
+                    p.backoff.reset();
+                    self.spawn(i);
+                }
+            }
+        }
+    }
+
+    /// Start process `i`'s replacement, keeping the running child until
+    /// the replacement is ready.
+    fn replace(&mut self, i: usize) {
+        let status = self.handle.status.lock().unwrap()[i].clone();
+        let p = &mut self.procs[i];
+        let child = p.child.take().unwrap();
+        let pid = child.id();
+        p.live = None;
+        p.watchdog = None;
+        p.previous = Some(Previous {
+            child,
+            readers: mem::take(&mut p.readers),
+            notify: p.notify.take(),
+            started: p.started,
+            ready: status.ready,
+            notify_status: status.notify_status,
+            stopping: false,
+            deadline: None,
+            signal: None,
+        });
+        self.log_process(i, format_args!("Starting replacement for {}", pid));
+        self.spawn(i);
+    }
+
+    /// Gracefully stop process `i`'s previous child, if it has one, once
+    /// the connections proxied so far have closed if `drain`.
+    fn retire(&mut self, i: usize, signal: Signal, drain: bool) {
+        let p = &self.procs[i];
+        let pid = match &p.previous {
+            Some(previous) if !previous.stopping => previous.child.id(),
+            _ => return,
+        };
+        let draining = drain && self.drain(i, pid);
+        let previous = self.procs[i].previous.as_mut().unwrap();
+        previous.stopping = true;
+        if draining {
+            previous.signal = Some(signal);
+            previous.deadline = Some(Instant::now() + self.opts.drain_timeout);
+            self.log_process(i, format_args!("Draining connections to previous {}", pid));
+            return;
+        }
+        self.stop_previous(i, signal);
+    }
+
+    /// Send `signal` to process `i`'s previous child, SIGKILL follows after
+    /// the stop timeout.
+    fn stop_previous(&mut self, i: usize, signal: Signal) {
+        let previous = self.procs[i].previous.as_mut().unwrap();
+        let pid = previous.child.id();
+        previous.signal = None;
+        previous.deadline = Some(Instant::now() + self.opts.stop_timeout);
+        self.log_process(i, format_args!("Sending {} to previous {}", signal, pid));
+        self.emit(Lifecycle::Stopping {
+            name: self.name(i),
+            pid,
+            signal,
+        });
+        if let Err(e) = signal.send(pid, self.opts.process_group) {
+            self.log_process(i, format_args!("Error {:?} stopping process", e));
+        }
+    }
+
+    /// Process `i`'s replacement failed, go back to its previous child
+    /// answering requests for the restart with `err`, unless there is none
+    /// still running.
+    fn revert(&mut self, i: usize, err: &str) -> bool {
+        let p = &mut self.procs[i];
+        let previous = match p.previous.take() {
+            Some(previous) if !previous.stopping => previous,
+            previous => {
+                p.previous = previous;
+                return false;
+            }
+        };
+        let pid = previous.child.id();
+        for reply in p.replies.drain(..).chain(p.wait.drain(..)) {
+            reply.send(Err(err.to_string()));
+        }
+        p.child = Some(previous.child);
+        p.readers = previous.readers;
+        p.notify = previous.notify;
+        p.watchdog = self.opts.watchdog.map(|timeout| Instant::now() + timeout);
+        p.started = previous.started;
+        p.state = State::Running;
+        {
+            let status = &mut self.handle.status.lock().unwrap()[i];
+            status.pid = Some(pid);
+            status.started = Some(SystemTime::now() - previous.started.elapsed());
+            status.ready = previous.ready;
+            status.notify_status = previous.notify_status;
+        }
+        self.log_process(i, format_args!("{}, keeping {}", err, pid));
+        self.watch_liveness(i, pid);
+        self.set_up(i, true);
+        true
+    }
+
+    /// Let proxied connections through to process `i`, or hold them.
+    fn set_up(&self, i: usize, up: bool) {
+        if let Some(gate) = &self.procs[i].gate {
+            gate.set_up(up);
+        }
+    }
+
+    /// Wait for the connections proxied so far to process `i` to close,
+    /// returning false if there are none to wait for. [`Event::Drained`]
+    /// follows for `pid` once they have.
+    fn drain(&self, i: usize, pid: u32) -> bool {
+        let tx = self.handle.tx.clone();
+        match &self.procs[i].gate {
+            Some(gate) => gate.drain(move || {
+                let _ = tx.send(Event::Drained(pid));
+            }),
+            None => false,
+        }
+    }
+
+    /// The proxied connections to `pid` have closed, stop it.
+    fn drained(&mut self, pid: u32) {
+        for i in 0..self.procs.len() {
+            let p = &self.procs[i];
+            if let Some(signal) = p
+                .previous
+                .as_ref()
+                .filter(|previous| previous.child.id() == pid)
+                .and_then(|previous| previous.signal)
+            {
+                self.stop_previous(i, signal);
+                return;
+            }
+            if let State::Draining { signal, then, .. } = p.state {
+                if p.pid() == pid {
+                    self.signal(i, signal, then);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Stop process `i`, deciding what happens once it exits. Proxied
+    /// connections are drained first unless forwarding `signal`.
+    fn stop(&mut self, i: usize, signal: Signal, then: Then) {
+        if then != Then::Restart {
+            self.retire(i, signal, then != Then::Forward);
+        }
+        let group = self.opts.process_group;
+        let p = &mut self.procs[i];
+        match &mut p.state {
+            State::Running => {
+                let pid = p.pid();
+                if let Some(gate) = &p.gate {
+                    gate.stopping();
+                }
+                self.set_up(i, false);
+                if then != Then::Forward && self.drain(i, pid) {
+                    self.procs[i].state = State::Draining {
+                        signal,
+                        deadline: Instant::now() + self.opts.drain_timeout,
+                        then,
+                    };
+                    self.log_process(i, "Draining connections...");
+                    return;
+                }
+                self.signal(i, signal, then);
+            }
+            State::Draining { then: current, .. } => {
+                if then != Then::Restart {
+                    *current = then;
+                }
+                if then == Then::Forward {
+                    self.signal(i, signal, then);
+                }
+            }
+            State::Stopping { then: current, .. } => {
+                if then != Then::Restart {
+                    *current = then;
+                }
+                if then == Then::Forward {
+                    let pid = p.pid();
+                    self.log_process(i, format_args!("Sending {} to {}", signal, pid));
+                    let _ = signal.send(pid, group);
+                }
+            }
+            State::Waiting { .. } => self.finish(
+                i,
+                match then {
+                    Then::Forward => signal.exit_code(),
+                    _ => 0,
+                },
+            ),
+            State::Done { code } => {
+                let code = *code;
+                self.finish(i, code);
+            }
+        }
+    }
+
+    /// Send `signal` to process `i`'s child, SIGKILL follows after the stop
+    /// timeout.
+    fn signal(&mut self, i: usize, signal: Signal, then: Then) {
+        let p = &mut self.procs[i];
+        let pid = p.pid();
+        p.state = State::Stopping {
+            signal,
+            deadline: Some(Instant::now() + self.opts.stop_timeout),
+            then,
+        };
+        self.log_process(i, format_args!("Sending {} to {}", signal, pid));
+        self.emit(Lifecycle::Stopping {
+            name: self.name(i),
+            pid,
+            signal,
+        });
+        if let Err(e) = signal.send(pid, self.opts.process_group) {
+            self.log_process(i, format_args!("Error {:?} stopping process", e));
+        }
+    }
+
+    /// Process `i` won't be restarted, answer anything waiting on it.
+    fn finish(&mut self, i: usize, code: i32) {
+        let p = &mut self.procs[i];
+        p.state = State::Done { code };
+        for reply in p.replies.drain(..) {
+            reply.send(Ok(()));
+        }
+        for reply in p.wait.drain(..) {
+            reply.send(Err("Stopped before becoming ready".to_string()));
+        }
+    }
+
+    /// Act on the processes' deadlines that have passed.
+    fn expire(&mut self) {
+        let now = Instant::now();
+        for i in 0..self.procs.len() {
+            let p = &mut self.procs[i];
+            if p.deadline().is_none_or(|deadline| deadline > now) {
+                continue;
+            }
+            if let Some(previous) = p
+                .previous
+                .as_mut()
+                .filter(|previous| previous.deadline.is_some_and(|deadline| deadline <= now))
+            {
+                previous.deadline = None;
+                let pid = previous.child.id();
+                match previous.signal {
+                    Some(signal) => {
+                        self.log_process(
+                            i,
+                            format_args!(
+                                "Drain timeout {:?} elapsed for previous {}",
+                                self.opts.drain_timeout, pid
+                            ),
+                        );
+                        self.stop_previous(i, signal);
+                    }
+                    None => {
+                        self.log_process(
+                            i,
+                            format_args!(
+                                "Stop timeout {:?} elapsed, sending SIGKILL to previous {}",
+                                self.opts.stop_timeout, pid
+                            ),
+                        );
+                        let _ = Signal::KILL.send(pid, self.opts.process_group);
+                    }
+                }
+                // Any other deadline that has passed is handled next time.
+                continue;
+            }
+            match &mut p.state {
+                State::Draining { signal, then, .. } => {
+                    let (signal, then) = (*signal, *then);
+                    self.log_process(
+                        i,
+                        format_args!("Drain timeout {:?} elapsed", self.opts.drain_timeout),
+                    );
+                    self.signal(i, signal, then);
+                }
+                State::Stopping { deadline, .. } => {
+                    *deadline = None;
+                    let pid = p.pid();
+                    self.log_process(
+                        i,
+                        format_args!(
+                            "Stop timeout {:?} elapsed, sending SIGKILL",
+                            self.opts.stop_timeout
+                        ),
+                    );
+                    let _ = Signal::KILL.send(pid, self.opts.process_group);
+                }
+                State::Waiting { .. } => self.spawn(i),
+                State::Running if p.watchdog.is_some_and(|deadline| deadline <= now) => {
+                    p.watchdog = None;
+                    let pid = p.pid();
+                    let reason = format!(
+                        "No watchdog ping within {:?}",
+                        self.opts.watchdog.unwrap_or_default()
+                    );
+                    self.log_process(i, &reason);
+                    self.emit(Lifecycle::Unhealthy {
+                        name: self.name(i),
+                        pid,
+                        reason,
+                    });
+                    let name = self.name(i);
+                    self.restart(Some(&name), None, false, false);
+                }
+                State::Running => {
+                    let err = format!("Not ready after {:?}", self.opts.ready_timeout);
+                    p.ready_deadline = None;
+                    p.probe = None;
+                    for reply in p.waiters.drain(..) {
+                        reply.send(Err(err.clone()));
+                    }
+                    if p.previous
+                        .as_ref()
+                        .is_some_and(|previous| !previous.stopping)
+                    {
+                        let pid = p.pid();
+                        p.state = State::Stopping {
+                            signal: Signal::KILL,
+                            deadline: None,
+                            then: Then::Revert,
+                        };
+                        self.log_process(i, format_args!("{}, sending SIGKILL to {}", err, pid));
+                        let _ = Signal::KILL.send(pid, self.opts.process_group);
+                    } else {
+                        self.log_process(i, err);
+                    }
+                }
+                State::Done { .. } => {}
+            }
+        }
+    }
+
+    /// The child `pid` passed its readiness probe.
+    fn ready(&mut self, pid: u32) {
+        let i = match self.procs.iter().position(|p| {
+            p.ready_deadline.is_some()
+                && matches!(p.state, State::Running)
+                && p.child.as_ref().is_some_and(|child| child.id() == pid)
+        }) {
+            Some(i) => i,
+            None => return,
+        };
+        let p = &mut self.procs[i];
+        p.ready_deadline = None;
+        p.probe = None;
+        p.watchdog = self.opts.watchdog.map(|timeout| Instant::now() + timeout);
+        for reply in p.waiters.drain(..) {
+            reply.send(Ok(()));
+        }
+        self.handle.status.lock().unwrap()[i].ready = Some(true);
+        self.log_process(i, "Ready");
+        self.emit(Lifecycle::Ready {
+            name: self.name(i),
+            pid,
+        });
+        self.watch_liveness(i, pid);
+        self.set_up(i, true);
+        self.retire(i, self.opts.stop_signal, true);
+    }
+
+    fn spawn(&mut self, i: usize) {
+        let process = &self.procs[i].process;
+        let sockets = &self.procs[i].sockets;
+        let mut command = match sockets.is_empty() {
+            true => {
+                let mut command = self.command(&process.command[0], Some(process));
+                command.args(&process.command[1..]);
+                command
+            }
+            false => {
+                // LISTEN_PID must be the child's own pid, known once forked.
+                let mut command = self.command("sh", Some(process));
+                command
+                    .arg("-c")
+                    .arg("LISTEN_PID=$$; export LISTEN_PID; exec \"$@\"")
+                    .arg("rstrtr")
+                    .args(&process.command)
+                    .env("LISTEN_FDS", sockets.len().to_string());
+                let fds: Vec<_> = sockets.iter().map(|fd| fd.as_raw_fd()).collect();
+                unsafe { command.pre_exec(move || listen::pass(&fds)) };
+                command
+            }
+        };
+        if self.opts.process_group {
+            command.process_group(0);
+        }
+        if self.output.piped() {
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+        let notify =
+            matches!(self.procs[i].ready, Some(Probe::Notify)) || self.opts.watchdog.is_some();
+        let spawned = match notify {
+            true => NotifySocket::bind()
+                .map_err(|e| format!("Error {:?} creating notify socket", e))
+                .map(Some),
+            false => Ok(None),
+        }
+        .and_then(|notify| {
+            if let Some(notify) = &notify {
+                command.env("NOTIFY_SOCKET", notify.path());
+            }
+            if let Some(watchdog) = self.opts.watchdog {
+                command.env("WATCHDOG_USEC", watchdog.as_micros().to_string());
+            }
+            command
+                .spawn()
+                .map(|child| (child, notify))
+                .map_err(|e| format!("Error {:?} executing command", e))
+        });
+        let (mut child, mut notify) = match spawned {
+            Ok(spawned) => spawned,
+            Err(err) => {
+                self.log_process(i, &err);
+                if self.revert(i, &err) {
+                    return;
+                }
+                let p = &mut self.procs[i];
+                for reply in p.replies.drain(..).chain(p.wait.drain(..)) {
+                    reply.send(Err(err.clone()));
+                }
+                self.finish(i, 1);
+                return;
+            }
+        };
+        let pid = child.id();
+        wait_exit(pid, self.handle.tx.clone());
+        if let Some(notify) = &mut notify {
+            let tx = self.handle.tx.clone();
+            notify.listen(move |notification| {
+                let _ = tx.send(Event::Notified(pid, notification));
+            });
+        }
+        let name = &self.procs[i].process.name;
+        let mut matched = match &self.procs[i].ready {
+            Some(Probe::Output(regex)) => Some((regex.clone(), self.handle.tx.clone())),
+            _ => None,
+        };
+        let mut readers = Vec::new();
+        if let Some(stdout) = child.stdout.take() {
+            readers.push(self.output.forward(i, name, false, stdout, move |line| {
+                if matched
+                    .as_ref()
+                    .is_some_and(|(regex, _)| regex.is_match(line))
+                {
+                    let (_, tx) = matched.take().unwrap();
+                    let _ = tx.send(Event::Ready(pid));
+                }
+            }));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            let gate = self.procs[i].gate.clone();
+            readers.push(self.output.forward(i, name, true, stderr, move |line| {
+                if let Some(gate) = &gate {
+                    gate.stderr(line);
+                }
+            }));
+        }
+        let probe = match &self.procs[i].ready {
+            Some(Probe::Output(_) | Probe::Notify) | None => None,
+            Some(ready) => {
+                let tx = self.handle.tx.clone();
+                Some(probe::poll(
+                    ready.clone(),
+                    self.probe_context(i, PROBE_TIMEOUT),
+                    self.opts.probe_interval,
+                    move || {
+                        let _ = tx.send(Event::Ready(pid));
+                    },
+                ))
+            }
+        };
+        self.emit(Lifecycle::Started {
+            name: self.name(i),
+            pid,
+        });
+
+        {
+            let status = &mut self.handle.status.lock().unwrap()[i];
+            if status.last_exit.is_some() || self.procs[i].previous.is_some() {
+                status.restarts += 1;
+                status.last_restart = Some(SystemTime::now());
+            }
+            status.pid = Some(pid);
+            status.started = Some(SystemTime::now());
+            status.ready = self.procs[i].ready.as_ref().map(|_| false);
+            status.notify_status = None;
+        }
+        if let Some(gate) = &self.procs[i].gate {
+            gate.started();
+        }
+        let p = &mut self.procs[i];
+        for reply in p.replies.drain(..) {
+            reply.send(Ok(()));
+        }
+        match p.ready {
+            Some(_) => {
+                p.waiters.append(&mut p.wait);
+                p.ready_deadline = Some(Instant::now() + self.opts.ready_timeout);
+            }
+            None => {
+                for reply in p.wait.drain(..) {
+                    reply.send(Ok(()));
+                }
+            }
+        }
+        p.probe = probe;
+        p.notify = notify;
+        p.child = Some(child);
+        p.readers = readers;
+        p.started = Instant::now();
+        p.state = State::Running;
+        if p.ready.is_none() {
+            p.watchdog = self.opts.watchdog.map(|timeout| Instant::now() + timeout);
+            self.watch_liveness(i, pid);
+            self.set_up(i, true);
+            self.retire(i, self.opts.stop_signal, true);
+        }
+    }
+
+    /// Start checking the liveness of process `i`'s child `pid`, if it has
+    /// a liveness probe.
+    fn watch_liveness(&mut self, i: usize, pid: u32) {
+        let liveness = match &self.procs[i].liveness {
+            Some(liveness) => liveness.clone(),
+            None => return,
+        };
+        let tx = self.handle.tx.clone();
+        self.procs[i].live = Some(probe::watch(
+            liveness,
+            self.probe_context(i, self.opts.liveness_timeout),
+            self.opts.liveness_interval,
+            self.opts.liveness_failures,
+            move |reason| {
+                let _ = tx.send(Event::Unhealthy(pid, reason));
+            },
+        ));
+    }
+
+    /// The child `pid` failed its liveness probe, restart it without
+    /// building so a failed build can't leave it hung.
+    fn unhealthy(&mut self, pid: u32, reason: String) {
+        let i = match self.procs.iter().position(|p| {
+            matches!(p.state, State::Running)
+                && p.child.as_ref().is_some_and(|child| child.id() == pid)
+        }) {
+            Some(i) => i,
+            None => return,
+        };
+        self.log_process(
+            i,
+            format_args!(
+                "Failed liveness probe {} times: {}",
+                self.opts.liveness_failures, reason
+            ),
+        );
+        self.emit(Lifecycle::Unhealthy {
+            name: self.name(i),
+            pid,
+            reason,
+        });
+        let name = self.name(i);
+        self.restart(Some(&name), None, false, false);
+    }
+
+    /// The child `pid` sent `notification` to its `NOTIFY_SOCKET`.
+    fn notified(&mut self, pid: u32, notification: Notification) {
+        let i = match self
+            .procs
+            .iter()
+            .position(|p| p.child.as_ref().is_some_and(|child| child.id() == pid))
+        {
+            Some(i) => i,
+            None => return,
+        };
+        match notification {
+            Notification::Ready => {
+                if let Some(Probe::Notify) = self.procs[i].ready {
+                    self.ready(pid);
+                }
+            }
+            Notification::Watchdog => {
+                let p = &mut self.procs[i];
+                if p.watchdog.is_some() {
+                    p.watchdog = self.opts.watchdog.map(|timeout| Instant::now() + timeout);
+                }
+            }
+            Notification::Status(status) => {
+                self.handle.status.lock().unwrap()[i].notify_status = Some(status);
+            }
+        }
+    }
+
+    /// Where probes of process `i` run, each check taking up to `timeout`.
+    fn probe_context(&self, i: usize, timeout: Duration) -> probe::Context {
+        let process = &self.procs[i].process;
+        probe::Context {
+            env: self.opts.env.iter().chain(&process.env).cloned().collect(),
+            dir: process
+                .current_dir
+                .clone()
+                .or_else(|| self.opts.current_dir.clone()),
+            timeout,
+        }
+    }
+
+    fn exited(&mut self, pid: u32) {
+        if let Some(i) = self.procs.iter().position(|p| {
+            p.previous
+                .as_ref()
+                .is_some_and(|previous| previous.child.id() == pid)
+        }) {
+            let previous = self.procs[i].previous.take().unwrap();
+            if let Some(exit) = self.reap(i, pid, previous.child, previous.readers) {
+                if previous.deadline.is_some() {
+                    self.log_process(i, format_args!("Stopped previous {}", pid));
+                }
+                self.log_process(i, format_args!("Exit {} from previous {}", exit, pid));
+                self.emit(Lifecycle::Exited {
+                    name: self.name(i),
+                    pid,
+                    status: exit,
+                });
+            }
+            return;
+        }
+
+        let i = match self
+            .procs
+            .iter()
+            .position(|p| p.child.as_ref().is_some_and(|child| child.id() == pid))
+        {
+            Some(i) => i,
+            None => return,
+        };
+        let p = &mut self.procs[i];
+        p.ready_deadline = None;
+        p.probe = None;
+        p.live = None;
+        p.notify = None;
+        p.watchdog = None;
+        for reply in p.waiters.drain(..) {
+            reply.send(Err("Exited before becoming ready".to_string()));
+        }
+        if p.previous.is_none() {
+            self.set_up(i, false);
+        }
+        let p = &mut self.procs[i];
+        let child = p.child.take().unwrap();
+        let readers = mem::take(&mut p.readers);
+        let exit = match self.reap(i, pid, child, readers) {
+            Some(exit) => exit,
+            None => {
+                self.finish(i, 1);
+                return;
+            }
+        };
+
+        let then = match mem::replace(&mut self.procs[i].state, State::Running) {
+            State::Stopping {
+                signal,
+                deadline,
+                then,
+            } => {
+                if deadline.is_some() {
+                    self.log_process(i, format_args!("Stopped by {}", signal));
+                }
+                Some(then)
+            }
+            State::Draining { then, .. } => Some(then),
+            State::Running | State::Waiting { .. } | State::Done { .. } => None,
+        };
+        self.record_exit(i, pid, exit);
+        match then {
+            Some(Then::Restart) => {
+                self.log_process(i, "Restarting...");
+                self.emit(Lifecycle::Restarting {
+                    name: self.name(i),
+                    delay: Duration::ZERO,
+                });
+                self.procs[i].backoff.reset();
+                self.spawn(i);
+            }
+            Some(Then::Quit) => self.finish(i, 0),
+            Some(Then::Forward) => self.finish(i, exit_code(exit)),
+            Some(Then::Revert) => {
+                if !self.revert(i, "Replacement not ready") {
+                    self.crashed(i, exit);
+                }
+            }
+            None => {
+                if !self.revert(i, "Replacement exited") {
+                    self.crashed(i, exit);
+                }
+            }
+        }
+    }
+
+    /// Reap process `i`'s exited `child`, once `readers` have written the
+    /// last of its output.
+    fn reap(
+        &self,
+        i: usize,
+        pid: u32,
+        mut child: Child,
+        readers: Vec<Receiver<()>>,
+    ) -> Option<ExitStatus> {
+        let exit = match child.wait() {
+            Ok(exit) => exit,
+            Err(e) => {
+                self.log_process(i, format_args!("Error {:?} waiting on process", e));
+                return None;
+            }
+        };
+        if self.opts.process_group {
+            // Catch stragglers left behind in the group by the command.
+            let _ = self.opts.stop_signal.send(pid, true);
+        }
+        // Let the last of its output be written before reporting the exit,
+        // unless something it started is still holding the pipes open.
+        let deadline = Instant::now() + Duration::from_millis(100);
+        for reader in readers {
+            let _ = reader.recv_timeout(deadline.saturating_duration_since(Instant::now()));
+        }
+        Some(exit)
+    }
+
+    /// Process `i` exited by itself, restart it after a delay if its
+    /// restart policy allows.
+    fn crashed(&mut self, i: usize, exit: ExitStatus) {
+        let p = &mut self.procs[i];
+        if !p.restart.should_restart(exit) {
+            self.finish(i, exit_code(exit));
+            return;
+        }
+        if let Some(max) = self.opts.max_restarts {
+            let now = Instant::now();
+            let window = self.opts.restart_window;
+            p.exits.push_back(now);
+            while p
+                .exits
+                .front()
+                .is_some_and(|t| now.duration_since(*t) > window)
+            {
+                p.exits.pop_front();
+            }
+            let exits = p.exits.len();
+            if exits > max {
+                self.log_process(
+                    i,
+                    format_args!("Exited {} times within {:?}, giving up", exits, window),
+                );
+                self.finish(i, 1);
+                return;
+            }
+        }
+        let delay = p.backoff.next(p.started.elapsed());
+        p.state = State::Waiting {
+            until: Instant::now() + delay,
+        };
+        self.log_process(i, format_args!("Restarting in {:?}...", delay));
+        self.emit(Lifecycle::Restarting {
+            name: self.name(i),
+            delay,
+        });
+    }
+
+    fn record_exit(&self, i: usize, pid: u32, exit: ExitStatus) {
+        self.log_process(i, format_args!("Exit {}", exit));
+        if let Some(gate) = &self.procs[i].gate {
+            gate.exited(exit.to_string());
+        }
+        self.emit(Lifecycle::Exited {
+            name: self.name(i),
+            pid,
+            status: exit,
+        });
+        let status = &mut self.handle.status.lock().unwrap()[i];
+        status.pid = None;
+        status.started = None;
+        status.ready = None;
+        status.notify_status = None;
+        status.last_exit = Some(exit.to_string());
+    }
+
+    /// A [`Command`] for `program` in the configured environment, and that
+    /// of `process` if given.
+    fn command(&self, program: &str, process: Option<&Process>) -> Command {
+        let mut command = Command::new(program);
+        let env = process.map_or(&[][..], |process| &process.env);
+        command.envs(
+            self.opts
+                .env
+                .iter()
+                .chain(env)
+                .map(|(key, value)| (key, value)),
+        );
+        if let Some(dir) = process
+            .and_then(|process| process.current_dir.as_ref())
+            .or(self.opts.current_dir.as_ref())
+        {
+            command.current_dir(dir);
+        }
+        unsafe { command.pre_exec(signal::unblock) };
+        command
+    }
+
+    /// Run the build command in the background, then make `restarts`
+    /// once [`Event::Built`] follows.
+    fn start_build(&mut self, restarts: Vec<Restart>) {
+        self.log("Building...");
+        let mut command = self.command("sh", None);
+        command.arg("-c").arg(self.opts.build.as_ref().unwrap());
+        if self.opts.process_group {
+            command.process_group(0);
+        }
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                self.build_failed(restarts, format!("Error {:?} executing build", e));
+                return;
+            }
+        };
+        let pid = child.id();
+        let tx = self.handle.tx.clone();
+        thread::spawn(move || {
+            let _ = tx.send(Event::Built(pid, child.wait()));
+        });
+        self.build = Some(Build {
+            pid,
+            restarts,
+            next: Vec::new(),
+        });
+    }
+
+    /// The build command `pid` exited, make the restarts waiting on it if it
+    /// succeeded, then build again for any asked for since.
+    fn built(&mut self, pid: u32, exit: io::Result<ExitStatus>) {
+        let build = match self.build.take() {
+            Some(build) if build.pid == pid => build,
+            build => {
+                self.build = build;
+                return;
+            }
+        };
+        match exit {
+            Ok(exit) if exit.success() => {
+                for restart in build.restarts {
+                    self.restart(restart.name.as_deref(), restart.reply, restart.wait, false);
+                }
+            }
+            Ok(exit) => self.build_failed(build.restarts, format!("Build failed {}", exit)),
+            Err(e) => self.build_failed(build.restarts, format!("Error {:?} waiting on build", e)),
+        }
+        if !build.next.is_empty() {
+            self.start_build(build.next);
+        }
+    }
+
+    fn build_failed(&self, restarts: Vec<Restart>, err: String) {
+        self.log(&err);
+        self.emit(Lifecycle::BuildFailed(err.clone()));
+        for restart in restarts {
+            if let Some(reply) = restart.reply {
+                reply.send(Err(err.clone()));
+            }
+        }
+    }
+
+    /// Stop building when quitting, sending `signal` to the build command.
+    fn cancel_build(&mut self, signal: Signal) {
+        let build = match self.build.take() {
+            Some(build) => build,
+            None => return,
+        };
+        let _ = signal.send(build.pid, self.opts.process_group);
+        for restart in build.restarts.into_iter().chain(build.next) {
+            if let Some(reply) = restart.reply {
+                reply.send(Err("Quitting".to_string()));
+            }
+        }
+    }
+}
+
+/// Send [`Event::Exited`] once the process `pid` exits, leaving it for the
+/// supervisor to reap so its pid can't be reused while still signalled.
+fn wait_exit(pid: u32, tx: Sender<Event>) {
+    thread::spawn(move || {
+        let mut info: libc::siginfo_t = unsafe { mem::zeroed() };
+        loop {
+            let res = unsafe {
+                libc::waitid(
+                    libc::P_PID,
+                    pid as libc::id_t,
+                    &mut info,
+                    libc::WEXITED | libc::WNOWAIT,
+                )
+            };
+            if res == 0 || io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                break;
+            }
+        }
+        let _ = tx.send(Event::Exited(pid));
+    });
+}
+
+/// Exit code for rstrtr to mirror the command's exit status.
+fn exit_code(exit: ExitStatus) -> i32 {
+    exit.code()
+        .unwrap_or_else(|| 128 + exit.signal().unwrap_or_default())
+}